@@ -0,0 +1,209 @@
+use crate::envelope::{Band, Follower};
+use crate::onset::OnsetDetector;
+use crate::spectrum::Stft;
+use crate::{full_scale, rms};
+use hound::WavSpec;
+use std::cmp;
+
+// fraction of spectral energy below the rolloff frequency
+const ROLLOFF: f64 = 0.85;
+// band edges in Hz
+const LOW_MID: f64 = 250.;
+const MID_HIGH: f64 = 4000.;
+
+/*
+ * Audio features of one frame.
+ * Frequencies are relative to nyquist, 0..1
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Features {
+    pub rms: f64,
+    // center of mass of the spectrum, brightness
+    pub centroid: f64,
+    // bandwidth around the centroid
+    pub spread: f64,
+    // 0 for tonal, 1 for white noise
+    pub flatness: f64,
+    pub rolloff: f64,
+    // spectral change since the previous frame
+    pub flux: f64,
+    // onset detection function, flux or rising rms
+    pub novelty: f64,
+    // a transient starts in this frame
+    pub onset: bool,
+    // smoothed band energies and rms
+    pub low: f64,
+    pub mid: f64,
+    pub high: f64,
+    pub envelope: f64,
+}
+
+impl Features {
+    pub fn band(&self, band: Band) -> f64 {
+        match band {
+            Band::Low => self.low,
+            Band::Mid => self.mid,
+            Band::High => self.high,
+            Band::Full => self.envelope,
+        }
+    }
+}
+
+/*
+ * Runs over consecutive blocks and keeps what's needed between frames
+ */
+pub struct Analyzer {
+    spec: WavSpec,
+    stft: Stft,
+    onsets: OnsetDetector,
+    // low, mid, high and rms
+    followers: [Follower; 4],
+    previous: Vec<f64>,
+}
+
+impl Analyzer {
+    pub fn new(spec: WavSpec, stft: Stft, onsets: OnsetDetector, follower: Follower) -> Self {
+        Self {
+            spec,
+            stft,
+            onsets,
+            followers: [
+                follower.clone(),
+                follower.clone(),
+                follower.clone(),
+                follower,
+            ],
+            previous: vec![],
+        }
+    }
+
+    pub fn analyze(&mut self, block: &[i32]) -> Features {
+        let spectrum = self.spectrum(block);
+        let (centroid, spread) = centroid_spread(&spectrum);
+        let rms = rms(block, &self.spec);
+        let flux = flux(&spectrum, &self.previous);
+        let novelty = self.onsets.novelty(flux, rms);
+        let (low, mid, high) = self.bands(&spectrum);
+        let features = Features {
+            rms,
+            centroid,
+            spread,
+            flatness: flatness(&spectrum),
+            rolloff: rolloff(&spectrum),
+            flux,
+            novelty: novelty.unwrap_or(0.),
+            onset: novelty.is_some_and(|novelty| self.onsets.detect(novelty)),
+            low: self.followers[0].follow(low),
+            mid: self.followers[1].follow(mid),
+            high: self.followers[2].follow(high),
+            envelope: self.followers[3].follow(rms),
+        };
+        self.previous = spectrum;
+        features
+    }
+
+    /*
+     * Energy of low, mid and high band
+     */
+    fn bands(&self, spectrum: &[f64]) -> (f64, f64, f64) {
+        let nyquist = self.spec.sample_rate as f64 / 2.;
+        let mut energy = [0.; 3];
+        for (k, m) in spectrum.iter().enumerate() {
+            let hz = frequency(k, spectrum.len()) * nyquist;
+            let band = if hz < LOW_MID {
+                0
+            } else if hz < MID_HIGH {
+                1
+            } else {
+                2
+            };
+            energy[band] += m * m;
+        }
+        (energy[0].sqrt(), energy[1].sqrt(), energy[2].sqrt())
+    }
+
+    /*
+     * Mean magnitude spectrum of the block, channels mixed down to mono
+     */
+    fn spectrum(&self, block: &[i32]) -> Vec<f64> {
+        let channels = cmp::max(self.spec.channels as usize, 1);
+        let scale = full_scale(&self.spec) * channels as f64;
+        let mono = block
+            .chunks(channels)
+            .map(|frame| frame.iter().map(|s| *s as f64).sum::<f64>() / scale)
+            .collect::<Vec<f64>>();
+        let spectra = self.stft.process(&mono);
+        let mut spectrum = vec![0.; self.stft.bins()];
+        for frame in spectra.iter() {
+            for (m, bin) in spectrum.iter_mut().zip(frame) {
+                *m += bin.norm() as f64 / spectra.len() as f64;
+            }
+        }
+        spectrum
+    }
+}
+
+fn frequency(bin: usize, bins: usize) -> f64 {
+    bin as f64 / cmp::max(bins - 1, 1) as f64
+}
+
+pub fn centroid_spread(spectrum: &[f64]) -> (f64, f64) {
+    let total: f64 = spectrum.iter().sum();
+    if total <= 0. {
+        return (0., 0.);
+    }
+    let bins = spectrum.len();
+    let centroid = spectrum
+        .iter()
+        .enumerate()
+        .map(|(k, m)| frequency(k, bins) * m)
+        .sum::<f64>()
+        / total;
+    let variance = spectrum
+        .iter()
+        .enumerate()
+        .map(|(k, m)| (frequency(k, bins) - centroid).powi(2) * m)
+        .sum::<f64>()
+        / total;
+    (centroid, variance.sqrt())
+}
+
+/*
+ * Geometric over arithmetic mean of the power spectrum
+ */
+pub fn flatness(spectrum: &[f64]) -> f64 {
+    let power = spectrum
+        .iter()
+        .map(|m| m * m + f64::EPSILON)
+        .collect::<Vec<f64>>();
+    let arithmetic = power.iter().sum::<f64>() / power.len() as f64;
+    let geometric = (power.iter().map(|p| p.ln()).sum::<f64>() / power.len() as f64).exp();
+    if spectrum.iter().all(|m| *m <= 0.) {
+        0.
+    } else {
+        (geometric / arithmetic).clamp(0., 1.)
+    }
+}
+
+pub fn rolloff(spectrum: &[f64]) -> f64 {
+    let total: f64 = spectrum.iter().map(|m| m * m).sum();
+    let mut energy = 0.;
+    for (k, m) in spectrum.iter().enumerate() {
+        energy += m * m;
+        if energy >= total * ROLLOFF {
+            return frequency(k, spectrum.len());
+        }
+    }
+    0.
+}
+
+/*
+ * Half-wave rectified spectral difference, only rising energy counts
+ */
+pub fn flux(spectrum: &[f64], previous: &[f64]) -> f64 {
+    spectrum
+        .iter()
+        .zip(previous.iter().chain(std::iter::repeat(&0.)))
+        .map(|(m, p)| (m - p).max(0.))
+        .sum()
+}
@@ -1,4 +1,16 @@
-use clap::Parser;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use degenerate::databend::Endian;
+use degenerate::envelope::{Band, GrowMode};
+use degenerate::generator::Waveform;
+use degenerate::level::Normalization;
+use degenerate::midi::MidiSource;
+use degenerate::onset::OnsetMode;
+use degenerate::spectrum::Window;
+use degenerate::stream::RawFormat;
+use degenerate::template::Template;
+use degenerate::FrameFormat;
+use std::fs;
+use std::path::Path;
 
 #[derive(Debug, Clone)]
 pub enum Method {
@@ -9,7 +21,7 @@ pub enum Method {
 }
 
 // to select a method by string for structopt
-fn parse_method(method: &str) -> Result<Method, String> {
+pub fn parse_method(method: &str) -> Result<Method, String> {
     match method {
         "arc" => Ok(Method::Arc),
         "curve" => Ok(Method::Curve),
@@ -19,13 +31,370 @@ fn parse_method(method: &str) -> Result<Method, String> {
     }
 }
 
+// render parameters open to modulation
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    M,
+    T,
+    Radius,
+    Size,
+    F1,
+    F2,
+}
+
+fn parse_target(target: &str) -> Result<Target, String> {
+    match target {
+        "m" => Ok(Target::M),
+        "t" => Ok(Target::T),
+        "radius" => Ok(Target::Radius),
+        "size" => Ok(Target::Size),
+        "f1" => Ok(Target::F1),
+        "f2" => Ok(Target::F2),
+        _ => Err(format!("Could not parse target {}", target)),
+    }
+}
+
+// what a control curve drives, render parameters or what changes between frames
+#[derive(Debug, Clone, PartialEq)]
+pub enum Control {
+    Render(Target),
+    Expansion,
+    ScaleImage,
+}
+
+fn parse_control(control: &str) -> Result<Control, String> {
+    match control {
+        "expansion" => Ok(Control::Expansion),
+        "scale_image" => Ok(Control::ScaleImage),
+        _ => parse_target(control).map(Control::Render),
+    }
+}
+
+/*
+ * Curve column bound to a parameter, "radius=zoom" sets the radius
+ * to the value of column zoom.
+ */
+#[derive(Debug, Clone)]
+pub struct CurveBinding {
+    pub control: Control,
+    pub column: String,
+}
+
+fn parse_curve_binding(binding: &str) -> Result<CurveBinding, String> {
+    let (control, column) = binding
+        .split_once('=')
+        .ok_or_else(|| format!("Could not parse curve binding {}", binding))?;
+    Ok(CurveBinding {
+        control: parse_control(control)?,
+        column: column.trim().to_lowercase(),
+    })
+}
+
+impl CurveBinding {
+    // columns named after a parameter bind to it
+    pub fn from_column(column: &str) -> Option<Self> {
+        parse_control(column).ok().map(|control| CurveBinding {
+            control,
+            column: column.to_string(),
+        })
+    }
+}
+
+fn parse_midi_source(source: &str) -> Result<MidiSource, String> {
+    match source {
+        "note" => Ok(MidiSource::Note),
+        "velocity" => Ok(MidiSource::Velocity),
+        _ => match source.strip_prefix("cc").map(|cc| cc.parse::<u8>()) {
+            Some(Ok(cc)) if cc < 128 => Ok(MidiSource::Controller(cc)),
+            _ => Err(format!("Could not parse MIDI source {}", source)),
+        },
+    }
+}
+
+/*
+ * MIDI value bound to a render parameter, "radius=velocity:500"
+ * adds velocity (0..1) times 500 to the radius.
+ * f1 and f2 pick the equation from the raw MIDI value.
+ */
+#[derive(Debug, Clone)]
+pub struct MidiBinding {
+    pub target: Target,
+    pub source: MidiSource,
+    pub scale: f64,
+}
+
+fn parse_midi_binding(binding: &str) -> Result<MidiBinding, String> {
+    let error = || format!("Could not parse MIDI binding {}", binding);
+    let (target, source) = binding.split_once('=').ok_or_else(error)?;
+    let (source, scale) = match source.split_once(':') {
+        Some((source, scale)) => (source, scale.parse::<f64>().map_err(|_| error())?),
+        None => (source, 1.),
+    };
+    Ok(MidiBinding {
+        target: parse_target(target)?,
+        source: parse_midi_source(source)?,
+        scale,
+    })
+}
+
+// what happens on a detected onset
+#[derive(Debug, Clone)]
+pub enum OnsetAction {
+    // swap f1 and f2, automatic ones step to the next equations
+    Switch,
+    // radius back to its initial value
+    Reset,
+    // flash the background
+    Flash,
+}
+
+fn parse_onset_action(action: &str) -> Result<OnsetAction, String> {
+    match action {
+        "switch" => Ok(OnsetAction::Switch),
+        "reset" => Ok(OnsetAction::Reset),
+        "flash" => Ok(OnsetAction::Flash),
+        _ => Err(format!("Could not parse onset action {}", action)),
+    }
+}
+
+// parameters locked to the beat
+#[derive(Debug, Clone, PartialEq)]
+pub enum BeatSync {
+    T,
+    M,
+    Radius,
+}
+
+fn parse_beat_sync(sync: &str) -> Result<BeatSync, String> {
+    match sync {
+        "t" => Ok(BeatSync::T),
+        "m" => Ok(BeatSync::M),
+        "radius" => Ok(BeatSync::Radius),
+        _ => Err(format!("Could not parse beat sync {}", sync)),
+    }
+}
+
+// what happens to frame files already in the output directory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Existing {
+    // keep complete frames, render truncated ones again
+    Skip,
+    Overwrite,
+    // stop at the first existing frame
+    Fail,
+}
+
+fn parse_overwrite(policy: &str) -> Result<Existing, String> {
+    match policy {
+        "skip" => Ok(Existing::Skip),
+        "overwrite" => Ok(Existing::Overwrite),
+        "fail" => Ok(Existing::Fail),
+        _ => Err(format!("Could not parse overwrite policy {}", policy)),
+    }
+}
+
+fn parse_onset_mode(mode: &str) -> Result<OnsetMode, String> {
+    match mode {
+        "flux" => Ok(OnsetMode::Flux),
+        "energy" => Ok(OnsetMode::Energy),
+        _ => Err(format!("Could not parse onset mode {}", mode)),
+    }
+}
+
+fn parse_normalization(mode: &str) -> Result<Normalization, String> {
+    match mode {
+        "block" => Ok(Normalization::Block),
+        "file" => Ok(Normalization::File),
+        "window" => Ok(Normalization::Window),
+        _ => Err(format!("Could not parse normalization {}", mode)),
+    }
+}
+
+fn parse_grow_mode(mode: &str) -> Result<GrowMode, String> {
+    match mode {
+        "add" => Ok(GrowMode::Add),
+        "oscillate" => Ok(GrowMode::Oscillate),
+        "rms" => Ok(GrowMode::Rms),
+        _ => Err(format!("Could not parse grow mode {}", mode)),
+    }
+}
+
+fn parse_band(band: &str) -> Result<Band, String> {
+    match band {
+        "low" => Ok(Band::Low),
+        "mid" => Ok(Band::Mid),
+        "high" => Ok(Band::High),
+        "rms" => Ok(Band::Full),
+        _ => Err(format!("Could not parse band {}", band)),
+    }
+}
+
+/*
+ * Position in the sound file: frame index ("120"), seconds ("12.5s"),
+ * minutes and seconds ("1:02.5"), hours ("1:01:02.5") or timecode ("00:01:02:10")
+ */
+#[derive(Debug, Clone)]
+pub struct Position {
+    seconds: f64,
+    frames: usize,
+}
+
+impl Position {
+    pub fn frame(&self, fps: usize) -> usize {
+        (self.seconds * fps as f64).round() as usize + self.frames
+    }
+}
+
+fn parse_position(position: &str) -> Result<Position, String> {
+    let error = || format!("Could not parse position {}", position);
+    if let Some(seconds) = position.strip_suffix('s') {
+        let seconds = seconds.parse::<f64>().map_err(|_| error())?;
+        if seconds < 0. {
+            return Err(error());
+        }
+        return Ok(Position { seconds, frames: 0 });
+    }
+    let parts = position.split(':').collect::<Vec<&str>>();
+    let whole = |part: &str| part.parse::<usize>().map_err(|_| error());
+    let seconds = |part: &str| match part.parse::<f64>() {
+        Ok(s) if s >= 0. => Ok(s),
+        _ => Err(error()),
+    };
+    match parts[..] {
+        [frames] => Ok(Position {
+            seconds: 0.,
+            frames: whole(frames)?,
+        }),
+        [m, s] => Ok(Position {
+            seconds: whole(m)? as f64 * 60. + seconds(s)?,
+            frames: 0,
+        }),
+        [h, m, s] => Ok(Position {
+            seconds: whole(h)? as f64 * 3600. + whole(m)? as f64 * 60. + seconds(s)?,
+            frames: 0,
+        }),
+        [h, m, s, f] => Ok(Position {
+            seconds: (whole(h)? * 3600 + whole(m)? * 60 + whole(s)?) as f64,
+            frames: whole(f)?,
+        }),
+        _ => Err(error()),
+    }
+}
+
+fn parse_raw_format(format: &str) -> Result<RawFormat, String> {
+    match format {
+        "u8" => Ok(RawFormat::U8),
+        "s16le" => Ok(RawFormat::S16Le),
+        "s16be" => Ok(RawFormat::S16Be),
+        "s24le" => Ok(RawFormat::S24Le),
+        "s32le" => Ok(RawFormat::S32Le),
+        "f32le" => Ok(RawFormat::F32Le),
+        _ => Err(format!("Could not parse raw format {}", format)),
+    }
+}
+
+fn parse_waveform(waveform: &str) -> Result<Waveform, String> {
+    match waveform {
+        "sine" => Ok(Waveform::Sine),
+        "chirp" => Ok(Waveform::Chirp),
+        "square" => Ok(Waveform::Square),
+        "white" => Ok(Waveform::White),
+        "pink" => Ok(Waveform::Pink),
+        "fm" => Ok(Waveform::Fm),
+        "am" => Ok(Waveform::Am),
+        _ => Err(format!("Could not parse generator {}", waveform)),
+    }
+}
+
+fn parse_endian(endian: &str) -> Result<Endian, String> {
+    match endian {
+        "little" => Ok(Endian::Little),
+        "big" => Ok(Endian::Big),
+        _ => Err(format!("Could not parse endianness {}", endian)),
+    }
+}
+
+// placeholders of frame file names
+pub const TEMPLATE_FIELDS: &[&str] = &[
+    "frame",
+    "time",
+    "t",
+    "radius",
+    "m",
+    "size",
+    "iterations",
+    "flash",
+    "f1",
+    "f2",
+    "method",
+];
+
+/*
+ * A file name without placeholders is a prefix as it used to be,
+ * the frame number goes before an image extension, PNG without one.
+ */
+fn parse_template(template: &str) -> Result<Template, String> {
+    if template.contains('{') || template.contains('}') {
+        return Template::parse(template, TEMPLATE_FIELDS);
+    }
+    let path = Path::new(template);
+    let template = match path.extension().map(|e| e.to_string_lossy()) {
+        // dots in prefixes like take2.v1_ are no extension
+        Some(extension) if FrameFormat::from_extension(&extension).is_ok() => format!(
+            "{}{{frame:06}}.{}",
+            path.with_extension("").display(),
+            extension
+        ),
+        _ => format!("{}{{frame:06}}.png", template),
+    };
+    Template::parse(&template, TEMPLATE_FIELDS)
+}
+
+fn parse_depth(depth: &str) -> Result<u8, String> {
+    match depth {
+        "8" => Ok(8),
+        "16" => Ok(16),
+        _ => Err(format!("Could not parse bit depth {}, use 8 or 16", depth)),
+    }
+}
+
+fn parse_window(window: &str) -> Result<Window, String> {
+    match window {
+        "rect" => Ok(Window::Rectangular),
+        "hann" => Ok(Window::Hann),
+        "blackman" => Ok(Window::Blackman),
+        _ => Err(format!("Could not parse window {}", window)),
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// write per-frame features of the sound to a CSV file instead of rendering
+    Analyze {
+        /// CSV file to write, the decoded sound goes next to it as <output>.sound
+        output: String,
+    },
+    /// render a PNG written by degenerate again from its embedded parameters
+    Rerender {
+        image: String,
+        /// PNG to write, next to the image by default
+        output: Option<String>,
+    },
+}
+
 #[derive(Debug, Parser)]
 #[command(
     name = "degenerate",
     about = "Generative and manipulative Images from arithmetic primitives and soundwaves",
-    version = env!("CARGO_PKG_VERSION")
+    version = env!("CARGO_PKG_VERSION"),
+    // -h is taken by --height
+    disable_help_flag = true
 )]
 pub struct Args {
+    #[arg(long, action = clap::ArgAction::Help)]
+    pub help: Option<bool>,
+
+    /// print sound, analysis and points to stderr, stdout may carry video
     #[arg(short, long)]
     pub debug: bool,
 
@@ -53,15 +422,113 @@ pub struct Args {
     #[arg(short, long, default_value = "0")]
     pub radius: f64,
 
+    /// radius added per frame, see grow_mode
     #[arg(short, long, default_value = "0")]
     pub grow: f64,
 
+    /// loop the rendered frames seamlessly,
+    /// t, radius, the sound, curves, timeline, MIDI and beats swing out and back
+    #[arg(long = "loop")]
+    pub seamless: bool,
+
+    /// radius factor per frame
     #[arg(short, long, default_value = "1.0")]
     pub expansion: f64,
 
+    /// how grow changes the radius: add, oscillate or rms
+    #[arg(long, value_parser = parse_grow_mode, default_value = "add")]
+    pub grow_mode: GrowMode,
+
+    /// oscillation period in seconds
+    #[arg(long, default_value = "4.0")]
+    pub grow_period: f64,
+
+    #[arg(long, default_value = "0")]
+    pub min_radius: f64,
+
+    /// 0 for no limit
+    #[arg(long, default_value = "0")]
+    pub max_radius: f64,
+
     #[arg(long)]
     pub combine_dots: bool,
 
+    /// left channel drives p1, right channel drives p2
+    #[arg(long)]
+    pub stereo: bool,
+
+    /// STFT window function
+    #[arg(long, value_parser = parse_window, default_value = "hann")]
+    pub window: Window,
+
+    /// STFT window size, 0 runs a single FFT over the whole block
+    #[arg(long, default_value = "0")]
+    pub window_size: usize,
+
+    /// STFT hop size, 0 for half the window size
+    #[arg(long, default_value = "0")]
+    pub hop: usize,
+
+    /// onset detection function
+    #[arg(long, value_parser = parse_onset_mode, default_value = "flux")]
+    pub onset: OnsetMode,
+
+    /// novelty over recent mean needed for an onset
+    #[arg(long, default_value = "1.5")]
+    pub onset_threshold: f64,
+
+    /// switch, reset and/or flash on onsets. switch swaps f1 and f2,
+    /// or steps automatic ones through the equations
+    #[arg(long, value_parser = parse_onset_action, value_delimiter = ',')]
+    pub onset_action: Vec<OnsetAction>,
+
+    /// tempo, 0 estimates it from the sound file
+    #[arg(long, default_value = "0")]
+    pub bpm: f64,
+
+    #[arg(long, default_value = "4")]
+    pub beats_per_bar: usize,
+
+    /// lock t, m and/or radius to the beat
+    #[arg(long, value_parser = parse_beat_sync, value_delimiter = ',')]
+    pub sync: Vec<BeatSync>,
+
+    /// strength of the beat pulse on m and radius
+    #[arg(long, default_value = "0.5")]
+    pub beat_depth: f64,
+
+    /// envelope attack time in seconds
+    #[arg(long, default_value = "0.01")]
+    pub attack: f64,
+
+    /// envelope release time in seconds
+    #[arg(long, default_value = "0.3")]
+    pub release: f64,
+
+    /// band envelope scaling size, or the dots while size is 0: low, mid, high or rms
+    #[arg(long, value_parser = parse_band)]
+    pub size_envelope: Option<Band>,
+
+    /// band envelope scaling radius: low, mid, high or rms
+    #[arg(long, value_parser = parse_band)]
+    pub radius_envelope: Option<Band>,
+
+    /// strength of the envelopes on size and radius
+    #[arg(long, default_value = "1.0")]
+    pub envelope_depth: f64,
+
+    /// sample normalization: block, file or window
+    #[arg(long, value_parser = parse_normalization, default_value = "block")]
+    pub normalize: Normalization,
+
+    /// window normalization length in seconds
+    #[arg(long, default_value = "2.0")]
+    pub normalize_window: f64,
+
+    /// noise gate, blocks peaking below this fraction of full scale are silenced
+    #[arg(long, default_value = "0.0")]
+    pub gate: f64,
+
     #[arg(short = 'M', long, value_parser = parse_method, default_value = "dot")]
     pub method: Method,
 
@@ -77,11 +544,31 @@ pub struct Args {
     #[arg(short, long, default_value = "/tmp")]
     pub outdir: String,
 
-    #[arg(long, default_value = "frame_")]
-    pub filename: String,
+    /// frame file name template, the extension picks the format: png, jpg,
+    /// webp, tiff or svg. Placeholders like {frame:06} or {t:.3} are
+    /// frame, time, t, radius, m, size, iterations, flash, f1, f2 and method
+    #[arg(long, value_parser = parse_template, default_value = "frame_{frame:06}.png")]
+    pub filename: Template,
+
+    /// bits per channel of PNG and TIFF frames, 8 or 16
+    #[arg(long, default_value = "8", value_parser = parse_depth)]
+    pub depth: u8,
+
+    /// existing frame files: skip, overwrite or fail
+    #[arg(long, value_parser = parse_overwrite, default_value = "overwrite")]
+    pub overwrite: Existing,
+
+    /// continue an interrupted render, same as --overwrite skip
+    #[arg(long)]
+    pub resume: bool,
+
+    /// frame, seconds ("12.5s") or timecode ("00:01:02:10")
+    #[arg(long, value_parser = parse_position, default_value = "0")]
+    pub start: Position,
 
+    /// extend each block into the next frame, fraction of a frame
     #[arg(long, default_value = "0")]
-    pub start: usize,
+    pub overlap: f64,
 
     #[arg(short, long, default_value = "0")]
     pub frames: usize,
@@ -89,6 +576,219 @@ pub struct Args {
     #[arg(long, default_value = "")]
     pub image: String,
 
+    /// standard MIDI file to modulate parameters with
+    #[arg(long, default_value = "")]
+    pub midi: String,
+
+    /// bind MIDI to parameters, target=source[:scale],
+    /// targets m, t, radius, size, f1, f2, sources note, velocity, cc0..cc127
+    #[arg(long, value_parser = parse_midi_binding, value_delimiter = ',')]
+    pub midi_map: Vec<MidiBinding>,
+
+    /// write all frames to one file instead of PNG files: .gif, .png or .apng
+    /// for animations, Y4M video otherwise, "-" for Y4M on stdout
+    #[arg(long, default_value = "")]
+    pub video: String,
+
+    /// write every frame as a page of one PDF file instead of PNG files
+    #[arg(long, default_value = "")]
+    pub pdf: String,
+
+    /// write frames as SVG files instead of PNG files
+    #[arg(long)]
+    pub svg: bool,
+
+    /// dither GIF frames to their palette
+    #[arg(long)]
+    pub dither: bool,
+
+    /// TOML or JSON keyframes for render parameters
+    #[arg(long, default_value = "")]
+    pub timeline: String,
+
+    /// CSV of control curves, first column frame or time, values replace parameters
+    #[arg(long, default_value = "")]
+    pub curves: String,
+
+    /// bind curve columns to parameters, target=column, targets m, t, radius, size,
+    /// expansion, f1, f2, scale_image. Columns named after a target bind to it
+    #[arg(long, value_parser = parse_curve_binding, value_delimiter = ',')]
+    pub curve_map: Vec<CurveBinding>,
+
+    /// test signal instead of the ramp when there is no sound file:
+    /// sine, chirp, square, white, pink, fm or am
+    #[arg(long, value_parser = parse_waveform)]
+    pub generator: Option<Waveform>,
+
+    /// generator frequency in Hz, chirp start
+    #[arg(long, default_value = "440")]
+    pub frequency: f64,
+
+    /// chirp end frequency in Hz
+    #[arg(long, default_value = "4000")]
+    pub end_frequency: f64,
+
+    /// generator amplitude, 0..1
+    #[arg(long, default_value = "0.8")]
+    pub amplitude: f64,
+
+    /// fm and am modulator frequency in Hz
+    #[arg(long, default_value = "110")]
+    pub modulator: f64,
+
+    /// fm modulation index, am depth
+    #[arg(long, default_value = "1")]
+    pub modulation_index: f64,
+
+    /// noise generator seed
+    #[arg(long, default_value = "0")]
+    pub seed: u64,
+
+    /// read the sound file's bytes as samples, whatever the file is
+    #[arg(long)]
+    pub databend: bool,
+
+    /// databending bytes per sample, 1..4
+    #[arg(long, default_value = "1", value_parser = clap::value_parser!(u8).range(1..=4))]
+    pub word_size: u8,
+
+    /// databending byte order: little or big
+    #[arg(long, value_parser = parse_endian, default_value = "little")]
+    pub endian: Endian,
+
+    /// databending reads words as unsigned
+    #[arg(long)]
+    pub unsigned: bool,
+
+    /// sample rate of raw PCM on stdin, generators and databending
+    #[arg(long, default_value = "44100")]
+    pub sample_rate: u32,
+
+    /// channels of raw PCM on stdin and databending
+    #[arg(long, default_value = "2")]
+    pub channels: u16,
+
+    /// sample format of raw PCM on stdin
+    #[arg(long, value_parser = parse_raw_format, default_value = "s16le")]
+    pub raw_format: RawFormat,
+
+    /// seconds of raw PCM on stdin, t is scaled as for a sound file this long.
+    /// Without it the stream is as long as --start and --frames reach
+    #[arg(long, default_value = "0")]
+    pub duration: f64,
+
+    /// per-frame features written by analyze, replaces the analysis of the sound file,
+    /// and decoding it while <features>.sound is there and the sound file unchanged
+    #[arg(long, default_value = "")]
+    pub features: String,
+
+    /// sound file, "-" reads raw PCM from stdin
     #[arg(default_value = "")]
     pub soundfile: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+// arguments naming files to read
+const INPUTS: &[&str] = &[
+    "soundfile",
+    "image",
+    "midi",
+    "timeline",
+    "curves",
+    "features",
+];
+
+/*
+ * The command line with every option spelled out, defaults included, and
+ * input files as absolute paths. It parses to the same Args from any
+ * directory, and when later versions change a default.
+ */
+pub fn resolve(argv: &[String]) -> Vec<String> {
+    let command = Args::command();
+    let matches = command.clone().get_matches_from(argv);
+    let mut resolved = argv[..1].to_vec();
+    let mut positional = vec![];
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        let values = match matches.get_raw(id) {
+            Some(values) => values.map(|value| value.to_string_lossy().into_owned()),
+            None => continue,
+        };
+        let values = values.map(
+            |value| match (INPUTS.contains(&id), fs::canonicalize(&value)) {
+                (true, Ok(path)) => path.to_string_lossy().into_owned(),
+                _ => value,
+            },
+        );
+        let name = match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => format!("--{}", long),
+            (None, Some(short)) => format!("-{}", short),
+            (None, None) => {
+                positional.extend(values);
+                continue;
+            }
+        };
+        match arg.get_action() {
+            ArgAction::Help | ArgAction::Version => {}
+            ArgAction::SetTrue => {
+                if values.eq(["true".to_string()]) {
+                    resolved.push(name);
+                }
+            }
+            // = keeps values starting with - apart from options
+            _ => resolved.extend(values.map(|value| format!("{}={}", name, value))),
+        }
+    }
+    resolved.push("--".to_string());
+    resolved.extend(positional);
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use degenerate::template::Value;
+
+    #[test]
+    fn position_minutes_seconds() {
+        let position = parse_position("1:02.5").unwrap();
+        assert_eq!(position.seconds, 62.5);
+        assert_eq!(position.frame(24), 1500);
+    }
+
+    #[test]
+    fn position_timecode() {
+        let position = parse_position("00:01:02:10").unwrap();
+        assert_eq!(position.seconds, 62.);
+        assert_eq!(position.frames, 10);
+        assert_eq!(position.frame(25), 1560);
+    }
+
+    #[test]
+    fn position_seconds() {
+        assert_eq!(parse_position("12.5s").unwrap().frame(30), 375);
+        assert_eq!(parse_position("1:01:02.5").unwrap().seconds, 3662.5);
+        assert_eq!(parse_position("42").unwrap().frame(30), 42);
+    }
+
+    #[test]
+    fn filename_prefix() {
+        let filename = |prefix| {
+            parse_template(prefix)
+                .unwrap()
+                .format(|_| Value::Integer(7))
+        };
+        assert_eq!(filename("frame_"), "frame_000007.png");
+        assert_eq!(filename("take2.v1_"), "take2.v1_000007.png");
+        assert_eq!(filename("shot_.jpg"), "shot_000007.jpg");
+    }
+
+    #[test]
+    fn position_errors() {
+        for position in ["-1s", "1:-2", "1:2:3:4:5", "a:02", ""] {
+            assert!(parse_position(position).is_err(), "{}", position);
+        }
+    }
 }
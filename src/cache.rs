@@ -0,0 +1,237 @@
+use crate::analysis::Features;
+use hound::{SampleFormat, WavSpec};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::time::UNIX_EPOCH;
+
+const HEADER: &str =
+    "frame,time,rms,centroid,spread,flatness,rolloff,flux,novelty,onset,low,mid,high,envelope";
+
+#[derive(Debug, Clone)]
+struct Row {
+    frame: usize,
+    // seconds
+    time: f64,
+    features: Features,
+}
+
+/*
+ * Per-frame features stored as CSV, one row per frame.
+ * Played back by time, so renders may use a different frame rate.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct FeatureCache {
+    rows: Vec<Row>,
+    // rows played back so far
+    cursor: usize,
+}
+
+impl FeatureCache {
+    pub fn push(&mut self, frame: usize, time: f64, features: Features) {
+        self.rows.push(Row {
+            frame,
+            time,
+            features,
+        });
+    }
+
+    pub fn save(&self, filename: &str) {
+        let mut csv = String::from(HEADER);
+        csv.push('\n');
+        for row in self.rows.iter() {
+            let f = &row.features;
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                row.frame,
+                row.time,
+                f.rms,
+                f.centroid,
+                f.spread,
+                f.flatness,
+                f.rolloff,
+                f.flux,
+                f.novelty,
+                f.onset as u8,
+                f.low,
+                f.mid,
+                f.high,
+                f.envelope
+            ));
+        }
+        fs::write(filename, csv).expect("Could not write feature file");
+    }
+
+    pub fn load(filename: &str) -> Self {
+        let csv = fs::read_to_string(filename).expect("Could not open feature file");
+        let mut lines = csv.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            panic!("Could not parse feature file {}, unknown header", filename);
+        }
+        let rows = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let values = line
+                    .split(',')
+                    .map(|value| value.trim().parse::<f64>())
+                    .collect::<Result<Vec<f64>, _>>()
+                    .ok()
+                    .filter(|values| values.len() == 14)
+                    .unwrap_or_else(|| panic!("Could not parse feature row {}", line));
+                Row {
+                    frame: values[0] as usize,
+                    time: values[1],
+                    features: Features {
+                        rms: values[2],
+                        centroid: values[3],
+                        spread: values[4],
+                        flatness: values[5],
+                        rolloff: values[6],
+                        flux: values[7],
+                        novelty: values[8],
+                        onset: values[9] > 0.,
+                        low: values[10],
+                        mid: values[11],
+                        high: values[12],
+                        envelope: values[13],
+                    },
+                }
+            })
+            .collect();
+        Self { rows, cursor: 0 }
+    }
+
+    // seconds up to the end of the last frame
+    pub fn duration(&self) -> f64 {
+        match self.rows[..] {
+            [] => 0.,
+            [ref last] => last.time,
+            [.., ref previous, ref last] => 2. * last.time - previous.time,
+        }
+    }
+
+    /*
+     * Features of the last frame at or before time, call with ascending times.
+     * Onsets of frames skipped since the previous call are kept.
+     */
+    pub fn advance(&mut self, time: f64) -> Features {
+        let mut onset = false;
+        while self.cursor < self.rows.len() && self.rows[self.cursor].time <= time {
+            onset |= self.rows[self.cursor].features.onset;
+            self.cursor += 1;
+        }
+        match self.cursor.checked_sub(1).map(|last| &self.rows[last]) {
+            Some(row) => Features {
+                onset,
+                ..row.features.clone()
+            },
+            None => Features::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    sample_rate: u32,
+    channels: u16,
+    bits_per_sample: u16,
+    float: bool,
+    // estimated tempo and first beat in seconds
+    tempo: Option<(f64, f64)>,
+    hash: String,
+    stamp: String,
+    samples: usize,
+}
+
+/*
+ * The sound behind a feature file as the renderer needs it: the decoded
+ * samples, the estimated tempo and the hash of the sound file. analyze writes
+ * it next to the CSV, so renders from the features skip decoding.
+ * A JSON header line is followed by the samples as 32 bit little endian.
+ */
+#[derive(Debug, Clone)]
+pub struct SoundCache {
+    pub spec: WavSpec,
+    pub samples: Vec<i32>,
+    // bpm and offset, None when the tempo was given
+    pub tempo: Option<(f64, f64)>,
+    pub hash: String,
+    // how the sound file was read, its size and modification time
+    pub stamp: String,
+}
+
+impl SoundCache {
+    // file next to the feature file
+    pub fn path(features: &str) -> String {
+        format!("{}.sound", features)
+    }
+
+    pub fn save(&self, filename: &str) {
+        let header = Header {
+            sample_rate: self.spec.sample_rate,
+            channels: self.spec.channels,
+            bits_per_sample: self.spec.bits_per_sample,
+            float: self.spec.sample_format == SampleFormat::Float,
+            tempo: self.tempo,
+            hash: self.hash.clone(),
+            stamp: self.stamp.clone(),
+            samples: self.samples.len(),
+        };
+        let file = fs::File::create(filename).expect("Could not write sound cache");
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{}", serde_json::to_string(&header).unwrap())
+            .and_then(|_| {
+                self.samples
+                    .iter()
+                    .try_for_each(|sample| writer.write_all(&sample.to_le_bytes()))
+            })
+            .and_then(|_| writer.flush())
+            .expect("Could not write sound cache");
+    }
+
+    // None without a cache file
+    pub fn load(filename: &str) -> Option<Self> {
+        let file = fs::File::open(filename).ok()?;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .expect("Could not read sound cache");
+        let header: Header = serde_json::from_str(&line)
+            .unwrap_or_else(|e| panic!("Could not parse sound cache {}: {}", filename, e));
+        let mut bytes = vec![0u8; header.samples * 4];
+        reader
+            .read_exact(&mut bytes)
+            .unwrap_or_else(|_| panic!("Could not read sound cache {}, truncated", filename));
+        Some(Self {
+            spec: WavSpec {
+                channels: header.channels,
+                sample_rate: header.sample_rate,
+                bits_per_sample: header.bits_per_sample,
+                sample_format: if header.float {
+                    SampleFormat::Float
+                } else {
+                    SampleFormat::Int
+                },
+            },
+            samples: bytes
+                .chunks(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            tempo: header.tempo,
+            hash: header.hash,
+            stamp: header.stamp,
+        })
+    }
+}
+
+// how a sound file is read, with its size and modification time, to notice changes
+pub fn stamp(filename: &str, reading: &str) -> String {
+    let metadata = fs::metadata(filename).expect("Could not open sound file");
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos());
+    format!("{} {} {}", reading, metadata.len(), modified)
+}
@@ -0,0 +1,91 @@
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Frame,
+    // seconds
+    Time,
+}
+
+#[derive(Debug, Clone)]
+struct Curve {
+    name: String,
+    // key and value of the rows with a value in this column
+    points: Vec<(f64, f64)>,
+}
+
+/*
+ * Control curves from a CSV file with a header row.
+ * The first column is "frame" or "time" in seconds, the others are curves.
+ * Values are interpolated linearly between rows and held before the first
+ * and after the last one, empty cells are skipped.
+ */
+#[derive(Debug, Clone)]
+pub struct Curves {
+    pub key: Key,
+    curves: Vec<Curve>,
+}
+
+impl Curves {
+    pub fn load(filename: &str) -> Self {
+        let csv = fs::read_to_string(filename).expect("Could not open curve file");
+        let mut lines = csv.lines().filter(|line| !line.trim().is_empty());
+        let header = lines.next().expect("Could not parse curve file, no header");
+        let mut names = header.split(',').map(|name| name.trim().to_lowercase());
+        let key = match names.next().as_deref() {
+            Some("frame") => Key::Frame,
+            Some("time") => Key::Time,
+            _ => panic!("Could not parse curve file, first column must be frame or time"),
+        };
+        let mut curves = names
+            .map(|name| Curve {
+                name,
+                points: vec![],
+            })
+            .collect::<Vec<Curve>>();
+
+        let parse = |value: &str| {
+            value
+                .parse::<f64>()
+                .unwrap_or_else(|_| panic!("Could not parse curve value {}", value))
+        };
+        for line in lines {
+            let mut cells = line.split(',').map(str::trim);
+            let position = parse(cells.next().unwrap_or_default());
+            for (curve, cell) in curves.iter_mut().zip(cells) {
+                if !cell.is_empty() {
+                    curve.points.push((position, parse(cell)));
+                }
+            }
+        }
+        for curve in curves.iter_mut() {
+            curve.points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        Self { key, curves }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.curves
+            .iter()
+            .map(|curve| curve.name.as_str())
+            .collect()
+    }
+
+    // value of the named curve at frame, None without such a curve or values
+    pub fn value(&self, name: &str, frame: usize, fps: usize) -> Option<f64> {
+        let position = match self.key {
+            Key::Frame => frame as f64,
+            Key::Time => frame as f64 / fps as f64,
+        };
+        let points = &self.curves.iter().find(|curve| curve.name == name)?.points;
+        let next = points.partition_point(|(key, _)| *key <= position);
+        match (
+            next.checked_sub(1).and_then(|i| points.get(i)),
+            points.get(next),
+        ) {
+            (None, None) => None,
+            (Some((_, value)), None) | (None, Some((_, value))) => Some(*value),
+            (Some((k0, v0)), Some((k1, v1))) => Some(v0 + (v1 - v0) * (position - k0) / (k1 - k0)),
+        }
+    }
+}
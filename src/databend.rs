@@ -0,0 +1,107 @@
+use hound::{SampleFormat, WavSpec};
+use std::fs;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+/*
+ * Any file read as if it was headerless PCM.
+ * Unsigned words are centered around zero like 8 bit WAV samples.
+ */
+#[derive(Debug, Clone)]
+pub struct Databend {
+    // bytes per word, 1..4
+    pub word_size: usize,
+    pub endian: Endian,
+    pub signed: bool,
+}
+
+impl Databend {
+    pub fn spec(&self, sample_rate: u32, channels: u16) -> WavSpec {
+        WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: self.word_size as u16 * 8,
+            sample_format: SampleFormat::Int,
+        }
+    }
+
+    pub fn load(&self, filename: &str) -> Vec<i32> {
+        let bytes = fs::read(filename).expect("Could not open file");
+        self.decode(&bytes)
+    }
+
+    // trailing bytes short of a word are dropped
+    pub fn decode(&self, bytes: &[u8]) -> Vec<i32> {
+        let bits = self.word_size as u32 * 8;
+        bytes
+            .chunks_exact(self.word_size)
+            .map(|word| {
+                let value = match self.endian {
+                    Endian::Little => word.iter().rev().fold(0u32, |v, b| v << 8 | *b as u32),
+                    Endian::Big => word.iter().fold(0u32, |v, b| v << 8 | *b as u32),
+                };
+                if self.signed {
+                    // sign extend from the top bit of the word
+                    ((value << (32 - bits)) as i32) >> (32 - bits)
+                } else {
+                    (value as i64 - (1i64 << (bits - 1))) as i32
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn databend(word_size: usize, endian: Endian, signed: bool) -> Databend {
+        Databend {
+            word_size,
+            endian,
+            signed,
+        }
+    }
+
+    #[test]
+    fn decode_signed() {
+        let bytes = [0x01, 0x80, 0xff, 0x7f];
+        assert_eq!(
+            databend(2, Endian::Little, true).decode(&bytes),
+            vec![-32767, 32767]
+        );
+        assert_eq!(
+            databend(2, Endian::Big, true).decode(&bytes),
+            vec![384, -129]
+        );
+        assert_eq!(
+            databend(3, Endian::Little, true).decode(&[0xff, 0xff, 0xff]),
+            vec![-1]
+        );
+    }
+
+    #[test]
+    fn decode_unsigned() {
+        // centered like 8 bit WAV samples
+        assert_eq!(
+            databend(1, Endian::Little, false).decode(&[0, 128, 255]),
+            vec![-128, 0, 127]
+        );
+        assert_eq!(
+            databend(4, Endian::Big, false).decode(&[0x80, 0, 0, 0]),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn decode_drops_partial_word() {
+        assert_eq!(
+            databend(2, Endian::Little, true).decode(&[1, 0, 2, 0, 3]),
+            vec![1, 2]
+        );
+    }
+}
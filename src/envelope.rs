@@ -0,0 +1,135 @@
+use std::f64::consts::PI;
+
+/*
+ * Attack/release envelope follower running at frame rate.
+ * Rises towards louder input with the attack time and falls back
+ * with the release time, both in seconds.
+ */
+#[derive(Debug, Clone)]
+pub struct Follower {
+    attack: f64,
+    release: f64,
+    value: f64,
+}
+
+impl Follower {
+    pub fn new(attack: f64, release: f64, fps: f64) -> Self {
+        Self {
+            attack: coefficient(attack, fps),
+            release: coefficient(release, fps),
+            value: 0.,
+        }
+    }
+
+    pub fn follow(&mut self, input: f64) -> f64 {
+        let coefficient = if input > self.value {
+            self.attack
+        } else {
+            self.release
+        };
+        self.value = input + coefficient * (self.value - input);
+        self.value
+    }
+}
+
+// per frame smoothing factor, 0 follows the input immediately
+fn coefficient(seconds: f64, fps: f64) -> f64 {
+    if seconds > 0. && fps > 0. {
+        (-1. / (seconds * fps)).exp()
+    } else {
+        0.
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Band {
+    Low,
+    Mid,
+    High,
+    // whole spectrum, smoothed rms
+    Full,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GrowMode {
+    // grow added every frame
+    Add,
+    // grow is the amplitude of a sine around the radius
+    Oscillate,
+    // grow times the block rms added every frame
+    Rms,
+}
+
+/*
+ * Radius over the frames of a render.
+ * Every frame the radius is multiplied by expansion and grows as set by
+ * the mode, then clamped to min..max. A max of 0 leaves it unbounded.
+ */
+#[derive(Debug, Clone)]
+pub struct Growth {
+    pub mode: GrowMode,
+    pub grow: f64,
+    pub expansion: f64,
+    pub min: f64,
+    pub max: f64,
+    // oscillation period in seconds
+    pub period: f64,
+    pub radius: f64,
+}
+
+impl Growth {
+    // radius of the frame at time
+    pub fn step(&mut self, time: f64, rms: f64) -> f64 {
+        self.radius *= self.expansion;
+        let offset = match self.mode {
+            GrowMode::Add => {
+                self.radius += self.grow;
+                0.
+            }
+            GrowMode::Oscillate if self.period > 0. => {
+                self.grow * (2. * PI * time / self.period).sin()
+            }
+            GrowMode::Oscillate => 0.,
+            GrowMode::Rms => {
+                self.radius += self.grow * rms;
+                0.
+            }
+        };
+        self.radius = self.clamp(self.radius);
+        self.clamp(self.radius + offset)
+    }
+
+    /*
+     * Radius at phase 0..1 of a loop of frames lasting seconds.
+     * Growth swings out and back, oscillation completes whole periods.
+     * Leaves the radius as it is.
+     */
+    pub fn looped(&self, phase: f64, frames: usize, seconds: f64, rms: f64) -> f64 {
+        let steps = swing(phase) * frames as f64 / 2.;
+        let radius = self.radius * self.expansion.powf(steps);
+        let radius = match self.mode {
+            GrowMode::Add => radius + self.grow * steps,
+            GrowMode::Oscillate if self.period > 0. => {
+                let periods = (seconds / self.period).round().max(1.);
+                radius + self.grow * (2. * PI * phase * periods).sin()
+            }
+            GrowMode::Oscillate => radius,
+            GrowMode::Rms => radius + self.grow * rms,
+        };
+        self.clamp(radius)
+    }
+
+    fn clamp(&self, radius: f64) -> f64 {
+        let radius = radius.max(self.min);
+        if self.max > 0. {
+            radius.min(self.max)
+        } else {
+            radius
+        }
+    }
+}
+
+// 0 at phase 0 and 1, 1 halfway, smooth across the loop point
+pub fn swing(phase: f64) -> f64 {
+    (1. - (2. * PI * phase).cos()) / 2.
+}
@@ -0,0 +1,620 @@
+use super::args::{Args, BeatSync, Command, Control, CurveBinding, OnsetAction, Target};
+use super::ghostweb::{load_image, Feed, EQUATIONS};
+use super::render::{Cycle, RenderConfig};
+use super::timeline::Timeline;
+use degenerate::analysis::{Analyzer, Features};
+use degenerate::cache::{stamp, FeatureCache, SoundCache};
+use degenerate::curves::Curves;
+use degenerate::databend::Databend;
+use degenerate::envelope::{swing, Follower, Growth};
+use degenerate::generator::Generator;
+use degenerate::level::Level;
+use degenerate::metadata::hash_file;
+use degenerate::midi::Midi;
+use degenerate::onset::OnsetDetector;
+use degenerate::spectrum::Stft;
+use degenerate::stream::PcmStream;
+use degenerate::tempo::Tempo;
+use degenerate::{frame_window, load_soundfile, ramp, Sound};
+use std::cmp;
+use std::io;
+use std::io::StdinLock;
+use std::ops::Range;
+
+// background brightness on onset and decay per frame
+const FLASH: f64 = 0.8;
+const FLASH_DECAY: f64 = 0.6;
+
+/*
+ * Where the samples come from: a sound file, raw PCM on stdin,
+ * a generator or, without any, a ramp.
+ */
+pub struct Source {
+    pub sound: Sound,
+    stream: Option<PcmStream<StdinLock<'static>>>,
+    pub tempo: Option<Tempo>,
+    pub start: usize,
+    // frames to render, 0 for a stream of unknown length
+    pub frames: usize,
+    // sha256 of the sound file, empty without one
+    pub hash: String,
+    // changes of the sound file, empty without one
+    stamp: String,
+}
+
+// how the sound file is turned into samples
+fn reading(args: &Args) -> String {
+    if args.databend {
+        format!(
+            "databend {} {:?} {} {} {}",
+            args.word_size, args.endian, !args.unsigned, args.sample_rate, args.channels
+        )
+    } else {
+        "decode".to_string()
+    }
+}
+
+impl Source {
+    /*
+     * sound_cache comes with a feature file, it replaces decoding the
+     * sound file while that is unchanged.
+     */
+    pub fn open(
+        args: &Args,
+        midi: &Option<Midi>,
+        cache: &Option<FeatureCache>,
+        sound_cache: Option<SoundCache>,
+    ) -> Self {
+        let start = args.start.frame(args.fps);
+        let streaming = args.soundfile == "-";
+        let stamp = if args.soundfile.is_empty() || streaming {
+            String::new()
+        } else {
+            stamp(&args.soundfile, &reading(args))
+        };
+        let cached = sound_cache.filter(|cached| {
+            let unchanged = cached.stamp == stamp;
+            if !unchanged {
+                eprintln!("{} changed since the analysis, decoding it", args.soundfile);
+            }
+            unchanged
+        });
+        let hash = match &cached {
+            _ if stamp.is_empty() => String::new(),
+            Some(cached) => cached.hash.clone(),
+            None => hash_file(&args.soundfile),
+        };
+        let stream = if streaming {
+            let spec = args.raw_format.spec(args.sample_rate, args.channels);
+            Some(PcmStream::new(
+                io::stdin().lock(),
+                args.raw_format,
+                spec,
+                args.fps,
+                args.overlap,
+            ))
+        } else {
+            None
+        };
+        let cached_tempo = cached.as_ref().and_then(|cached| cached.tempo);
+        let sound = if let Some(stream) = &stream {
+            // samples arrive per frame, length is unknown up front
+            let rate = stream.spec.sample_rate as f64;
+            let duration = if args.duration > 0. {
+                args.duration * rate
+            } else if args.frames > 0 {
+                (start + args.frames) as f64 * rate / args.fps as f64
+            } else {
+                panic!("Could not scale t for a stream, give --frames or --duration")
+            };
+            Sound {
+                frames: args.frames,
+                // in samples per channel, as for sound files
+                duration,
+                spec: stream.spec,
+                samples: vec![],
+            }
+        } else if let (true, Some(waveform)) = (args.soundfile.is_empty(), args.generator) {
+            let spec = hound::WavSpec {
+                channels: 1,
+                sample_rate: args.sample_rate,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let frames = synthetic_frames(args, midi, cache);
+            let (length, _) = frame_window(start + frames, spec.sample_rate, args.fps, 0.);
+            let generator = Generator {
+                waveform,
+                frequency: args.frequency,
+                end_frequency: args.end_frequency,
+                amplitude: args.amplitude,
+                modulator: args.modulator,
+                index: args.modulation_index,
+                seed: args.seed,
+            };
+            Sound {
+                frames,
+                // stands in for a sound file, same t scale
+                duration: length as f64,
+                spec,
+                samples: generator.generate(spec.sample_rate, length),
+            }
+        } else if args.soundfile.is_empty() {
+            let blocksize = 255;
+            let frames = synthetic_frames(args, midi, cache);
+            Sound {
+                frames,
+                duration: frames as f64 / args.fps as f64,
+                spec: hound::WavSpec {
+                    channels: 1,
+                    sample_rate: (blocksize * args.fps) as u32,
+                    bits_per_sample: 32,
+                    sample_format: hound::SampleFormat::Int,
+                },
+                samples: ramp(blocksize * (start + frames)),
+            }
+        } else if let Some(cached) = cached {
+            Sound::new(cached.spec, cached.samples, args.fps, args.frames)
+        } else if args.databend {
+            let databend = Databend {
+                word_size: args.word_size as usize,
+                endian: args.endian,
+                signed: !args.unsigned,
+            };
+            Sound::new(
+                databend.spec(args.sample_rate, args.channels),
+                databend.load(&args.soundfile),
+                args.fps,
+                args.frames,
+            )
+        } else {
+            load_soundfile(args.soundfile.clone(), args.fps, args.frames, args.debug)
+        };
+        let tempo = if args.bpm > 0. {
+            Some(Tempo::new(args.bpm, 0., args.beats_per_bar))
+        } else if let Some((bpm, offset)) = cached_tempo {
+            Some(Tempo::new(bpm, offset, args.beats_per_bar))
+        } else if !args.soundfile.is_empty() && !streaming {
+            Some(Tempo::estimate(
+                &sound.samples,
+                &sound.spec,
+                args.beats_per_bar,
+            ))
+        } else {
+            None
+        };
+        if args.debug {
+            eprintln!("tempo: {:?}", tempo);
+        }
+        let frames = if streaming {
+            sound.frames
+        } else {
+            cmp::min(
+                sound.frames,
+                sound.frame_count(args.fps).saturating_sub(start),
+            )
+        };
+        Self {
+            sound,
+            stream,
+            tempo,
+            start,
+            frames,
+            hash,
+            stamp,
+        }
+    }
+
+    // what analyze keeps for renders from its features, None without a sound file
+    pub fn into_cache(self, args: &Args) -> Option<SoundCache> {
+        if self.stamp.is_empty() {
+            return None;
+        }
+        Some(SoundCache {
+            spec: self.sound.spec,
+            samples: self.sound.samples,
+            tempo: match self.tempo {
+                Some(tempo) if args.bpm <= 0. => Some((tempo.bpm, tempo.offset)),
+                _ => None,
+            },
+            hash: self.hash,
+            stamp: self.stamp,
+        })
+    }
+
+    // a stream without a frame count is read until it ends
+    pub fn is_open(&self) -> bool {
+        self.stream.is_some() && self.frames == 0
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        if self.is_open() {
+            self.start..usize::MAX
+        } else {
+            self.start..self.start + self.frames
+        }
+    }
+
+    // interleaved samples of frame i, None once the stream ends
+    fn block(&mut self, i: usize, fps: usize, overlap: f64) -> Option<Vec<i32>> {
+        match self.stream.as_mut() {
+            Some(stream) => stream.block(i),
+            None => Some(self.sound.block(i, fps, overlap).to_vec()),
+        }
+    }
+}
+
+// frames to render without a sound file
+fn synthetic_frames(args: &Args, midi: &Option<Midi>, cache: &Option<FeatureCache>) -> usize {
+    let duration = match (midi, cache) {
+        _ if args.frames > 0 => return args.frames,
+        // as long as the feature file
+        (_, Some(cache)) => cache.duration(),
+        // as long as the MIDI file
+        (Some(midi), None) => midi.duration,
+        (None, None) => return 1,
+    };
+    cmp::max((duration * args.fps as f64).ceil() as usize, 1)
+}
+
+// a frame ready to render
+pub struct Frame {
+    pub index: usize,
+    // seconds
+    pub time: f64,
+    pub config: RenderConfig,
+    // image feed scale and how far the equations displace it, 0..1
+    pub scale_image: f64,
+    pub strength: f64,
+}
+
+/*
+ * Render state carried from frame to frame: analysis, growth, onset actions
+ * and the modulation sources, resolved into one RenderConfig per frame.
+ * Frames must be resolved in ascending order.
+ */
+pub struct Frames<'a> {
+    args: &'a Args,
+    pub source: Source,
+    // image feed, empty without image
+    pub xs: Vec<Feed>,
+    iterations: u32,
+    midi: Option<Midi>,
+    curves: Option<Curves>,
+    curve_map: Vec<CurveBinding>,
+    timeline: Option<Timeline>,
+    // features played back instead of analyzed
+    cache: Option<FeatureCache>,
+    analyzer: Analyzer,
+    level: Level,
+    growth: Growth,
+    initial_radius: f64,
+    flash: f64,
+    // switch onsets so far
+    switches: usize,
+    // frames of a seamless loop, unknown for open streams
+    cycle: Option<usize>,
+    // blocks and features of a loop's first half, analyzed forward once
+    looped: Vec<(Vec<i32>, Option<f64>, Features)>,
+}
+
+impl<'a> Frames<'a> {
+    pub fn new(args: &'a Args) -> Self {
+        let radius = if args.radius > 0. {
+            args.radius
+        } else {
+            (args.width / 2) as f64
+        };
+        let growth = Growth {
+            mode: args.grow_mode,
+            grow: args.grow,
+            expansion: args.expansion,
+            min: args.min_radius,
+            max: args.max_radius,
+            period: args.grow_period,
+            radius,
+        };
+        let image = if args.image.is_empty() {
+            None
+        } else {
+            // scaled per frame, scale_image may follow a curve
+            load_image(&args.image, 1.)
+        };
+        let (is, xs): (u32, Vec<Feed>) = match image {
+            None => (0, vec![]),
+            Some((is, xs)) => (is, xs),
+        };
+        let iterations = if args.iterations > 0 {
+            args.iterations
+        } else {
+            match is {
+                0 => args.width * args.height,
+                _ => is,
+            }
+        };
+
+        let midi = if args.midi.is_empty() {
+            None
+        } else {
+            Some(Midi::load(&args.midi))
+        };
+        let curves = if args.curves.is_empty() {
+            None
+        } else {
+            Some(Curves::load(&args.curves))
+        };
+        let mut curve_map = args.curve_map.clone();
+        if let Some(curves) = &curves {
+            for name in curves.names() {
+                if !curve_map.iter().any(|binding| binding.column == name) {
+                    curve_map.extend(CurveBinding::from_column(name));
+                }
+            }
+        }
+        let timeline = if args.timeline.is_empty() {
+            None
+        } else {
+            Some(Timeline::load(&args.timeline))
+        };
+        let mut cache = if args.features.is_empty() {
+            None
+        } else {
+            Some(FeatureCache::load(&args.features))
+        };
+        // analyze writes features instead of playing them back
+        let analyzing = matches!(args.command, Some(Command::Analyze { .. }));
+        let sound_cache = match cache {
+            Some(_) if !analyzing => SoundCache::load(&SoundCache::path(&args.features)),
+            _ => None,
+        };
+        let source = Source::open(args, &midi, &cache, sound_cache);
+        if analyzing {
+            cache = None;
+        }
+
+        let analyzer = Analyzer::new(
+            source.sound.spec,
+            Stft::new(
+                args.window,
+                if args.window_size > 0 {
+                    args.window_size
+                } else {
+                    1024
+                },
+                args.hop,
+            ),
+            OnsetDetector::new(args.onset, args.onset_threshold),
+            Follower::new(args.attack, args.release, args.fps as f64),
+        );
+        let level = Level::new(
+            args.normalize,
+            source.sound.spec,
+            &source.sound.samples,
+            (args.normalize_window * args.fps as f64).round() as usize,
+            args.gate,
+        );
+        if args.seamless && args.soundfile == "-" {
+            panic!("Could not loop a stream, stdin can only be read forward");
+        }
+        if args.seamless && !args.onset_action.is_empty() {
+            panic!("Could not loop with onset actions, they carry over the loop point");
+        }
+        let cycle = if args.seamless && source.frames > 0 {
+            Some(source.frames)
+        } else {
+            None
+        };
+
+        Self {
+            args,
+            source,
+            xs,
+            iterations,
+            midi,
+            curves,
+            curve_map,
+            timeline,
+            cache,
+            analyzer,
+            level,
+            growth,
+            initial_radius: radius,
+            flash: 0.,
+            switches: 0,
+            cycle,
+            looped: vec![],
+        }
+    }
+
+    // gated block of frame i, its normalization range and features, None once a stream ends
+    pub fn analyze(&mut self, i: usize) -> Option<(Vec<i32>, Option<f64>, Features)> {
+        let args = self.args;
+        let time = i as f64 / args.fps as f64;
+        let mut block = self.source.block(i, args.fps, args.overlap)?;
+        if self.level.gated(&block) {
+            block.fill(0);
+        }
+        let range = self.level.range(&block);
+        let features = match self.cache.as_mut() {
+            Some(cache) => cache.advance(time),
+            None => self.analyzer.analyze(&block),
+        };
+        if args.debug {
+            eprintln!("{:?}", features);
+        }
+        Some((block, range, features))
+    }
+
+    // analysis of frame i of a loop, the swing comes back over frames analyzed before
+    fn looped(&mut self, i: usize) -> Option<(Vec<i32>, Option<f64>, Features)> {
+        let start = self.source.start;
+        while self.looped.len() <= i - start {
+            let analysis = self.analyze(start + self.looped.len())?;
+            self.looped.push(analysis);
+        }
+        Some(self.looped[i - start].clone())
+    }
+
+    // all parameters of frame i, None once a stream ends
+    pub fn resolve(&mut self, i: usize) -> Option<Frame> {
+        let args = self.args;
+        let fps = args.fps as f64;
+        let start = self.source.start;
+        let time = i as f64 / fps;
+        // half a loop, t swings there and back
+        let half = self.cycle.map_or(0., |frames| frames as f64 / 2. / fps);
+        let phase = self.cycle.map(|frames| (i - start) as f64 / frames as f64);
+        let t_time = match phase {
+            Some(phase) => start as f64 / fps + half * swing(phase),
+            None => time,
+        };
+        // frame of t_time, the sound, curves, timeline, MIDI and beats follow the loop
+        let t_frame = (t_time * fps).round() as usize;
+        let tempo = self.source.tempo;
+        let beat_time = match tempo {
+            Some(tempo) if args.sync.contains(&BeatSync::T) => tempo.warp(t_time),
+            _ => t_time,
+        };
+        // curve values of this frame replace the arguments
+        let controls: Vec<(Control, f64)> = match &self.curves {
+            Some(curves) => self
+                .curve_map
+                .iter()
+                .filter_map(|binding| {
+                    let value = curves.value(&binding.column, t_frame, args.fps)?;
+                    Some((binding.control.clone(), value))
+                })
+                .collect(),
+            None => vec![],
+        };
+        let control = |wanted: Control| {
+            controls
+                .iter()
+                .rev()
+                .find(|(control, _)| *control == wanted)
+                .map(|(_, value)| *value)
+        };
+        let t_factor = fps / self.source.sound.duration
+            * control(Control::Render(Target::T)).unwrap_or(args.t);
+        let t = beat_time * t_factor;
+
+        let (block, range, features) = match self.cycle {
+            Some(_) => self.looped(t_frame)?,
+            None => self.analyze(i)?,
+        };
+        self.growth.expansion = control(Control::Expansion).unwrap_or(args.expansion);
+        let mut radius = match (self.cycle, phase) {
+            (Some(frames), Some(phase)) => {
+                self.growth.looped(phase, frames, 2. * half, features.rms)
+            }
+            _ => self.growth.step(time, features.rms),
+        };
+        if let Some(value) = control(Control::Render(Target::Radius)) {
+            self.growth.radius = value;
+            radius = value;
+        }
+        let midi_state = self.midi.as_mut().map(|midi| midi.advance(t_time));
+        self.flash *= FLASH_DECAY;
+        if features.onset || midi_state.as_ref().is_some_and(|state| state.onset) {
+            for action in args.onset_action.iter() {
+                match action {
+                    OnsetAction::Switch => self.switches += 1,
+                    OnsetAction::Reset => {
+                        self.growth.radius = self.initial_radius;
+                        radius = self.initial_radius;
+                    }
+                    OnsetAction::Flash => self.flash = FLASH,
+                }
+            }
+        }
+
+        let mut config = RenderConfig::new(
+            self.iterations,
+            radius,
+            block,
+            self.source.sound.spec,
+            features.clone(),
+            t,
+            args,
+        );
+        for target in [Target::M, Target::Size, Target::F1, Target::F2] {
+            if let Some(value) = control(Control::Render(target.clone())) {
+                config.set(&target, value);
+            }
+        }
+        if let Some(timeline) = &self.timeline {
+            timeline.apply(t_frame, &mut config);
+        }
+        if let Some(band) = args.size_envelope {
+            let scale = 1. + args.envelope_depth * features.band(band);
+            if config.size > 0. {
+                config.size *= scale;
+            } else {
+                config.dot *= scale;
+            }
+        }
+        if let Some(band) = args.radius_envelope {
+            config.radius *= 1. + args.envelope_depth * features.band(band);
+        }
+        match self.switches {
+            0 => {}
+            switches if config.f1 > 0 && config.f2 > 0 => {
+                if switches % 2 == 1 {
+                    std::mem::swap(&mut config.f1, &mut config.f2);
+                }
+            }
+            // automatic equations step through the concrete ones, 1..EQUATIONS
+            switches => {
+                if config.f1 == 0 {
+                    config.f1 = (switches - 1) % (EQUATIONS - 1) + 1;
+                }
+                if config.f2 == 0 {
+                    config.f2 = switches % (EQUATIONS - 1) + 1;
+                }
+            }
+        }
+        config.flash = config.flash.max(self.flash);
+        config.range = range;
+        config.cycle = phase.map(|phase| Cycle {
+            phase,
+            span: half * t_factor,
+        });
+        if let Some(state) = &midi_state {
+            for binding in args.midi_map.iter() {
+                match binding.target {
+                    Target::F1 | Target::F2 => {
+                        // keep the equation while no note is held
+                        if state.raw(binding.source) > 0 {
+                            config.modulate(&binding.target, state.raw(binding.source) as f64)
+                        }
+                    }
+                    _ => config
+                        .modulate(&binding.target, state.value(binding.source) * binding.scale),
+                }
+            }
+        }
+        if let Some(tempo) = tempo {
+            config.beat = tempo.beat_phase(t_time);
+            config.bar = tempo.bar_phase(t_time);
+            let pulse = 1. + args.beat_depth * tempo.pulse(t_time);
+            if args.sync.contains(&BeatSync::M) {
+                config.m *= pulse;
+            }
+            if args.sync.contains(&BeatSync::Radius) {
+                config.radius *= pulse;
+            }
+        }
+
+        Some(Frame {
+            index: i,
+            time,
+            config,
+            scale_image: control(Control::ScaleImage).unwrap_or(args.scale_image),
+            strength: match phase {
+                Some(phase) => swing(phase),
+                None if self.source.frames > 0 => i as f64 / self.source.frames as f64,
+                None => 1.,
+            },
+        })
+    }
+}
@@ -0,0 +1,79 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Waveform {
+    Sine,
+    // linear sweep from frequency to end_frequency
+    Chirp,
+    Square,
+    White,
+    Pink,
+    // carrier at frequency, phase modulated at modulator with index
+    Fm,
+    // carrier at frequency, amplitude modulated at modulator with depth index
+    Am,
+}
+
+/*
+ * Synthetic test signal.
+ * Noise is seeded, the same settings always give the same samples.
+ */
+#[derive(Debug, Clone)]
+pub struct Generator {
+    pub waveform: Waveform,
+    pub frequency: f64,
+    pub end_frequency: f64,
+    pub amplitude: f64,
+    pub modulator: f64,
+    pub index: f64,
+    pub seed: u64,
+}
+
+impl Generator {
+    // mono 16 bit samples
+    pub fn generate(&self, sample_rate: u32, length: usize) -> Vec<i32> {
+        let rate = sample_rate as f64;
+        let duration = length as f64 / rate;
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        // Paul Kellet's pink noise filter state
+        let mut pink = [0f64; 7];
+
+        (0..length)
+            .map(|n| {
+                let t = n as f64 / rate;
+                let value = match self.waveform {
+                    Waveform::Sine => (2. * PI * self.frequency * t).sin(),
+                    Waveform::Chirp => {
+                        let sweep = (self.end_frequency - self.frequency) / (2. * duration);
+                        (2. * PI * (self.frequency * t + sweep * t * t)).sin()
+                    }
+                    Waveform::Square => (2. * PI * self.frequency * t).sin().signum(),
+                    Waveform::White => rng.gen_range(-1.0..1.0),
+                    Waveform::Pink => {
+                        let white: f64 = rng.gen_range(-1.0..1.0);
+                        pink[0] = 0.99886 * pink[0] + white * 0.0555179;
+                        pink[1] = 0.99332 * pink[1] + white * 0.0750759;
+                        pink[2] = 0.96900 * pink[2] + white * 0.1538520;
+                        pink[3] = 0.86650 * pink[3] + white * 0.3104856;
+                        pink[4] = 0.55000 * pink[4] + white * 0.5329522;
+                        pink[5] = -0.7616 * pink[5] - white * 0.0168980;
+                        let value = pink[..6].iter().sum::<f64>() + pink[6] + white * 0.5362;
+                        pink[6] = white * 0.115926;
+                        // roughly back to -1..1
+                        value * 0.11
+                    }
+                    Waveform::Fm => (2. * PI * self.frequency * t
+                        + self.index * (2. * PI * self.modulator * t).sin())
+                    .sin(),
+                    Waveform::Am => {
+                        let envelope = 1. + self.index * (2. * PI * self.modulator * t).sin();
+                        envelope / (1. + self.index.abs()) * (2. * PI * self.frequency * t).sin()
+                    }
+                };
+                ((value * self.amplitude).clamp(-1., 1.) * i16::MAX as f64) as i32
+            })
+            .collect()
+    }
+}
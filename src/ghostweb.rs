@@ -1,5 +1,8 @@
 pub use crate::feed::{Feed, Point};
-use degenerate::{fft, normalize, rms_32 as rms};
+use crate::render::{Cycle, RenderConfig};
+use degenerate::spectrum::Stft;
+use degenerate::{deinterleave, fft, normalize, range};
+use hound::WavSpec;
 use noise::{Billow, HybridMulti, NoiseFn, OpenSimplex};
 use rustfft::num_complex::Complex;
 use std::cmp;
@@ -8,6 +11,9 @@ use std::path::Path;
 
 const PHI: f64 = 1.618033988749;
 
+// number of equations select_equation knows
+pub const EQUATIONS: usize = 22;
+
 /*
  * State passed between iterations
  */
@@ -20,6 +26,14 @@ struct State {
 
     pub sample: f64,
     pub fft_bin: Complex<f32>,
+    pub magnitude: f64,
+    pub phase: f64,
+
+    // per channel values in stereo mode
+    pub sample_l: f64,
+    pub sample_r: f64,
+    pub fft_bin_l: Complex<f32>,
+    pub fft_bin_r: Complex<f32>,
 
     pub c: f64,
     pub c2: f64,
@@ -41,46 +55,140 @@ struct State {
     pub billow: Billow,
 }
 
+/*
+ * Normalized samples and spectrum of a single channel
+ */
+struct Stream {
+    samples: Vec<f64>,
+    fft: Vec<Complex<f32>>,
+}
+
+impl Stream {
+    fn new(block: &[i32], spec: &WavSpec, level: Option<f64>, stft: &Option<Stft>) -> Self {
+        let samples = normalize(block, level.unwrap_or_else(|| range(block, spec)));
+        Self {
+            fft: match stft {
+                Some(stft) => stft.per_sample(&samples),
+                None => fft(samples.to_owned()),
+            },
+            samples,
+        }
+    }
+}
+
+impl State {
+    fn set_bin(&mut self, bin: Complex<f32>) {
+        self.fft_bin = bin;
+        self.magnitude = bin.norm() as f64;
+        self.phase = bin.arg() as f64;
+    }
+}
+
 /*
  * Initialization State
  */
 struct Parameter {
     iterations: u32,
-    samples: Vec<f64>,
-    fft: Vec<Complex<f32>>,
+    main: Stream,
+    // left and right channel in stereo mode
+    stereo: Option<(Stream, Stream)>,
     radius: f64,
     m: f64,
     t: f64,
     rms: f64,
+    centroid: f64,
+    spread: f64,
+    flatness: f64,
+    rolloff: f64,
+    flux: f64,
+    // smoothed band energies
+    low: f64,
+    mid: f64,
+    high: f64,
+    // position in beat and bar, 0..1
+    beat: f64,
+    bar: f64,
+    cycle: Option<Cycle>,
 }
 
-pub fn ghostweb(
-    iterations: u32,
-    block: &[i32],
-    radius: f64,
-    f1: usize,
-    f2: usize,
-    m: f64,
-    t: f64,
-) -> Vec<Feed> {
+impl Parameter {
+    /*
+     * Noise at point with t, times scale, at index slot.
+     * In loop mode t moves on a circle in a fourth dimension instead,
+     * so the last frame meets the first.
+     */
+    fn noise<N>(&self, noise: &N, point: [f64; 3], slot: usize, scale: f64) -> f64
+    where
+        N: NoiseFn<[f64; 3]> + NoiseFn<[f64; 4]>,
+    {
+        match self.cycle {
+            None => noise.get(point),
+            Some(cycle) => {
+                let angle = 2. * PI * cycle.phase;
+                let radius = cycle.span * scale / 2.;
+                let mut rest = (0..3).filter(|i| *i != slot).map(|i| point[i]);
+                noise.get([
+                    rest.next().unwrap(),
+                    rest.next().unwrap(),
+                    radius * angle.cos(),
+                    radius * angle.sin(),
+                ])
+            }
+        }
+    }
+}
+
+pub fn ghostweb(conf: &RenderConfig) -> Vec<Feed> {
     // collected points
     let mut xs: Vec<Feed> = vec![];
-    let samples = normalize(block);
+    let block = &conf.block;
+    let spec = &conf.spec;
+    let stereo = if conf.stereo {
+        let channels = deinterleave(block, spec.channels as usize);
+        let left = Stream::new(&channels[0], spec, conf.range, &conf.stft);
+        // mono files drive both points from the same channel
+        let right = Stream::new(
+            channels.get(1).unwrap_or(&channels[0]),
+            spec,
+            conf.range,
+            &conf.stft,
+        );
+        Some((left, right))
+    } else {
+        None
+    };
 
     let params = Parameter {
-        iterations,
-        samples: samples.to_owned(),
-        fft: fft(samples),
-        radius,
-        m,
-        t,
-        rms: rms(block),
+        iterations: conf.iterations,
+        main: Stream::new(block, spec, conf.range, &conf.stft),
+        stereo,
+        radius: conf.radius,
+        m: conf.m,
+        t: conf.t,
+        rms: conf.features.rms,
+        centroid: conf.features.centroid,
+        spread: conf.features.spread,
+        flatness: conf.features.flatness,
+        rolloff: conf.features.rolloff,
+        flux: conf.features.flux,
+        low: conf.features.low,
+        mid: conf.features.mid,
+        high: conf.features.high,
+        beat: conf.beat,
+        bar: conf.bar,
+        cycle: conf.cycle,
     };
     let mut state = State {
         i: 0,
         index: 0,
         sample: 0.,
         fft_bin: Complex { re: 0., im: 0. },
+        magnitude: 0.,
+        phase: 0.,
+        sample_l: 0.,
+        sample_r: 0.,
+        fft_bin_l: Complex { re: 0., im: 0. },
+        fft_bin_r: Complex { re: 0., im: 0. },
         c: 0.,
         c2: 0.,
         c3: 0.,
@@ -100,24 +208,32 @@ pub fn ghostweb(
         osx: OpenSimplex::new(),
         hbm: HybridMulti::new(),
         billow: Billow::new(),
-        r: radius,
+        r: conf.radius,
     };
 
-    for i in 0..iterations {
+    for i in 0..conf.iterations {
         state = advance(i, state, &params);
 
-        let equation_1 = select_equation(if f1 > 0 {
-            f1
+        if params.stereo.is_some() {
+            state.sample = state.sample_l;
+            state.set_bin(state.fft_bin_l);
+        }
+        let equation_1 = select_equation(if conf.f1 > 0 {
+            conf.f1
         } else {
             (state.sample.abs() * 14.) as usize + 4
         });
-        let equation_2 = select_equation(if f2 > 0 {
-            f2
+        state.p1 = equation_1(&state, &params, &state.p1, &state.p2);
+
+        if params.stereo.is_some() {
+            state.sample = state.sample_r;
+            state.set_bin(state.fft_bin_r);
+        }
+        let equation_2 = select_equation(if conf.f2 > 0 {
+            conf.f2
         } else {
             (state.fft_bin.im.abs() * 14.) as usize + 4
         });
-
-        state.p1 = equation_1(&state, &params, &state.p1, &state.p2);
         state.p2 = equation_2(&state, &params, &state.p2, &state.p1);
 
         xs.push(Feed {
@@ -132,15 +248,28 @@ pub fn ghostweb(
 fn advance(i: u32, mut state: State, p: &Parameter) -> State {
     let part = i as f64 / p.iterations as f64;
 
-    if p.samples.len() > 0 {
-        let index = i as usize % p.samples.len();
-        state.sample = p.samples[index];
-        state.fft_bin = p.fft[index];
+    if !p.main.samples.is_empty() {
+        let index = i as usize % p.main.samples.len();
+        state.sample = p.main.samples[index];
+        state.set_bin(p.main.fft[index]);
         state.index = index;
     } else {
         state.sample = 0.;
     }
 
+    if let Some((left, right)) = &p.stereo {
+        if !left.samples.is_empty() {
+            let index = i as usize % left.samples.len();
+            state.sample_l = left.samples[index];
+            state.fft_bin_l = left.fft[index];
+        }
+        if !right.samples.is_empty() {
+            let index = i as usize % right.samples.len();
+            state.sample_r = right.samples[index];
+            state.fft_bin_r = right.fft[index];
+        }
+    }
+
     state.i = i;
     state.c = part * PI * 2.0;
     state.c2 = state.c * E;
@@ -179,21 +308,21 @@ fn equation_003(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
     let x: f64 =
         (p.t + s.c * p2.z).sin() * (s.c2 * p.t.powf(s.c3)).cos() * s.hbm.get([p2.x, p2.y, p2.z]);
     let y: f64 = (p.t * E + s.c).sin() * (p.rms - p.t.powf(s.sample)).sin();
-    let z: f64 = s.sample * s.osx.get([p1.x, p1.y, p.t]);
+    let z: f64 = s.sample * p.noise(&s.osx, [p1.x, p1.y, p.t], 2, 1.);
     Point { x, y, z }
 }
 
 fn equation_004(s: &State, p: &Parameter, p1: &Point, _p2: &Point) -> Point {
     let x = ((s.c2 + p.t) + p1.z + s.n + p.rms).sin();
-    let y = (s.c3 + p.t).cos() * s.billow.get([p1.x, p1.y, p.t * 2000.]);
+    let y = (s.c3 + p.t).cos() * p.noise(&s.billow, [p1.x, p1.y, p.t * 2000.], 2, 2000.);
     let z = (s.sample * p.rms + p.t).sin() * s.c;
     Point { x, y, z }
 }
 
 fn equation_005(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
     let x = (s.c3 * s.r * s.n).sin() + (s.c * p.t - s.c2).cos() - (p2.z + E).ln().sin();
-    let y = s.c3.cos() * (s.n * p1.z + p.rms).cos() + (p.t - (s.sample as f64).powf(E)).sin();
-    let z = s.hbm.get([p1.x, p1.y, s.sample as f64]) + s.billow.get([p2.x, p2.y, p2.z]) * s.sample;
+    let y = s.c3.cos() * (s.n * p1.z + p.rms).cos() + (p.t - s.sample.powf(E)).sin();
+    let z = s.hbm.get([p1.x, p1.y, s.sample]) + s.billow.get([p2.x, p2.y, p2.z]) * s.sample;
     Point { x, y, z }
 }
 
@@ -207,13 +336,13 @@ fn equation_006(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
         + s.c3.sqrt() * s.c2.cos())
     .tanh();
     if x.is_nan() {
-        x = s.osx.get([p1.x, p1.y, p.t])
+        x = p.noise(&s.osx, [p1.x, p1.y, p.t], 2, 1.)
     };
     if y.is_nan() {
-        y = s.osx.get([p1.x, p.t, p1.z])
+        y = p.noise(&s.osx, [p1.x, p.t, p1.z], 1, 1.)
     };
     if z.is_nan() {
-        z = s.osx.get([p.t, p1.y, p1.z])
+        z = p.noise(&s.osx, [p.t, p1.y, p1.z], 0, 1.)
     };
     Point { x, y, z }
 }
@@ -224,13 +353,13 @@ fn equation_007(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
         (s.c.cos() + (s.c2 * p2.x).sin() * p1.x * p2.z + (p1.z.abs() + p2.z.abs()).sqrt()).tanh();
     let mut z = ((1. / s.c2).sinh() * PI / p.t.powf(s.c.cos() * E) + s.c3.sin()).tanh();
     if x.is_nan() {
-        x = s.hbm.get([p.t, p1.y, p1.z])
+        x = p.noise(&s.hbm, [p.t, p1.y, p1.z], 0, 1.)
     };
     if y.is_nan() {
-        y = s.hbm.get([p1.x, p.t, p1.z])
+        y = p.noise(&s.hbm, [p1.x, p.t, p1.z], 1, 1.)
     };
     if z.is_nan() {
-        z = s.hbm.get([p1.x, p1.y, p.t])
+        z = p.noise(&s.hbm, [p1.x, p1.y, p.t], 2, 1.)
     };
     Point { x, y, z }
 }
@@ -253,13 +382,13 @@ fn equation_009(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
     .tanh();
     let mut z = (x.powf(s.c2) * y.powf(s.c3) - (p2.x + p1.x + p.rms).cos()).tanh();
     if x.is_nan() {
-        x = s.hbm.get([p1.x, p1.y, p.t])
+        x = p.noise(&s.hbm, [p1.x, p1.y, p.t], 2, 1.)
     };
     if y.is_nan() {
-        y = s.hbm.get([p1.x, p.t, p1.z])
+        y = p.noise(&s.hbm, [p1.x, p.t, p1.z], 1, 1.)
     };
     if z.is_nan() {
-        z = s.hbm.get([p.t, p1.y, p1.z])
+        z = p.noise(&s.hbm, [p.t, p1.y, p1.z], 0, 1.)
     };
     Point { x, y, z }
 }
@@ -280,22 +409,22 @@ fn equation_010(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
     .tanh())
         * s.n;
     if x.is_nan() {
-        x = s.osx.get([p.t, p1.y, p1.z])
+        x = p.noise(&s.osx, [p.t, p1.y, p1.z], 0, 1.)
     };
     if y.is_nan() {
-        y = s.osx.get([p1.x, p.t, p1.z])
+        y = p.noise(&s.osx, [p1.x, p.t, p1.z], 1, 1.)
     };
     if z.is_nan() {
-        z = s.osx.get([p1.x, p1.y, p.t])
+        z = p.noise(&s.osx, [p1.x, p1.y, p.t], 2, 1.)
     };
     if !x.is_finite() {
-        x = s.hbm.get([p.t, p1.y, p1.z])
+        x = p.noise(&s.hbm, [p.t, p1.y, p1.z], 0, 1.)
     };
     if !y.is_finite() {
-        y = s.hbm.get([p1.x, p.t, p1.z])
+        y = p.noise(&s.hbm, [p1.x, p.t, p1.z], 1, 1.)
     };
     if !z.is_finite() {
-        z = s.hbm.get([p1.x, p1.y, p.t])
+        z = p.noise(&s.hbm, [p1.x, p1.y, p.t], 2, 1.)
     };
     Point { x, y, z }
 }
@@ -316,7 +445,7 @@ fn equation_012(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
     let y = s.sample * 1. / (p1.z.abs() * s.c2 + p.t).ln()
         * (x * PI + p1.z * E + p2.z * SQRT_2).sin()
         - s.fft_bin.im as f64;
-    let z = s.osx.get([s.sample, p.t, x]);
+    let z = p.noise(&s.osx, [s.sample, p.t, x], 1, 1.);
     Point { x, y, z }
 }
 
@@ -333,22 +462,38 @@ fn equation_013(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
     let mut z = x * s.fft_bin.re as f64 * s.sample
         + s.fft_bin.re as f64 * (p.t * PI + (x - p1.x) + (y - p1.y)).cos();
     if x.is_nan() {
-        x = s.osx.get([p2.x, p2.y, p.t])
+        x = p.noise(&s.osx, [p2.x, p2.y, p.t], 2, 1.)
     };
     if y.is_nan() {
-        y = s.osx.get([p1.x, p.t, p1.z])
+        y = p.noise(&s.osx, [p1.x, p.t, p1.z], 1, 1.)
     };
     if z.is_nan() {
-        z = s.osx.get([p.t, p2.y, p2.z])
+        z = p.noise(&s.osx, [p.t, p2.y, p2.z], 0, 1.)
     };
     Point { x, y, z }
 }
 
 fn equation_014(s: &State, p: &Parameter, _p1: &Point, _p2: &Point) -> Point {
-    let x =
-        s.c3.sin() + (s.c * PHI * s.osx.get([p.t, s.fft_bin.im as f64, s.fft_bin.re as f64])).cos();
-    let y = s.c3.cos() + (s.c2 * s.hbm.get([p.t, s.fft_bin.im as f64, s.fft_bin.re as f64])).sin();
-    let z = s.billow.get([p.t, x, y]);
+    let x = s.c3.sin()
+        + (s.c
+            * PHI
+            * p.noise(
+                &s.osx,
+                [p.t, s.fft_bin.im as f64, s.fft_bin.re as f64],
+                0,
+                1.,
+            ))
+        .cos();
+    let y = s.c3.cos()
+        + (s.c2
+            * p.noise(
+                &s.hbm,
+                [p.t, s.fft_bin.im as f64, s.fft_bin.re as f64],
+                0,
+                1.,
+            ))
+        .sin();
+    let z = p.noise(&s.billow, [p.t, x, y], 0, 1.);
     Point { x, y, z }
 }
 
@@ -356,8 +501,8 @@ fn equation_014(s: &State, p: &Parameter, _p1: &Point, _p2: &Point) -> Point {
 fn equation_015(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
     let xd = p1.x - p2.x;
     let yd = p1.y - p2.y;
-    let x = xd + s.c * (3. as f64 * p1.y).tan().sin();
-    let y = p1.y - p2.y + s.n * (3. as f64 * p1.y).tan().sin();
+    let x = xd + s.c * (3. * p1.y).tan().sin();
+    let y = p1.y - p2.y + s.n * (3. * p1.y).tan().sin();
     let z = (p2.z - p1.z) + s.c3 * p.t * (x + y).cos();
     Point {
         x: if x.abs() <= 1.0 {
@@ -428,6 +573,38 @@ fn equation_018(s: &State, p: &Parameter, _p1: &Point, _p2: &Point) -> Point {
     Point { x, y, z }
 }
 
+// bright and noisy sounds open up, tonal ones stay on the circle
+fn equation_019(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
+    let x = ((s.c * (1. + p.centroid * 8.)).sin() * (1. - p.flatness)
+        + p1.z * p.spread * s.magnitude)
+        .tanh();
+    let y = ((s.c * (1. + p.rolloff * 8.) + s.phase).cos() * (1. - p.flatness)
+        + p2.z * p.spread * s.magnitude)
+        .tanh();
+    let z = (p.flux * PI + p.t + s.sample).sin() * p.flatness.max(p.rms);
+    Point { x, y, z }
+}
+
+// rings breathing with the beat, turning once per bar
+fn equation_020(s: &State, p: &Parameter, p1: &Point, _p2: &Point) -> Point {
+    let pulse = (1. - p.beat).powi(2);
+    let angle = s.c + p.bar * PI * 2.;
+    let r = 0.5 + 0.5 * pulse * (s.c3 + p.t).sin() + s.sample * p.rms;
+    let x = angle.cos() * r;
+    let y = angle.sin() * r;
+    let z = (p1.z * (1. - pulse) + p.noise(&s.osx, [x, y, p.t], 2, 1.) * pulse).tanh();
+    Point { x, y, z }
+}
+
+// three bands on three axes, bass swells, highs shimmer
+fn equation_021(s: &State, p: &Parameter, p1: &Point, p2: &Point) -> Point {
+    let r = 0.3 + p.low;
+    let x = (s.c + p.t).cos() * r + p.mid * (s.c3 * 2.).sin() * p2.z;
+    let y = (s.c + p.t).sin() * r + p.mid * (s.c2 * 2.).cos() * p2.z;
+    let z = (p1.z + p.high * p.noise(&s.billow, [x, y, p.t], 2, 1.)).tanh();
+    Point { x, y, z }
+}
+
 fn select_equation(index: usize) -> fn(&State, &Parameter, p1: &Point, p2: &Point) -> Point {
     match index {
         1 => equation_001,
@@ -448,6 +625,9 @@ fn select_equation(index: usize) -> fn(&State, &Parameter, p1: &Point, p2: &Poin
         16 => equation_016,
         17 => equation_017,
         18 => equation_018,
+        19 => equation_019,
+        20 => equation_020,
+        21 => equation_021,
         _ => equation_000,
     }
 }
@@ -481,7 +661,7 @@ pub fn image_to_points(image: image::GrayImage, scale: f64) -> Vec<Feed> {
 pub fn load_image(image_file_path: &String, scale: f64) -> Option<(u32, Vec<Feed>)> {
     let path = Path::new(image_file_path);
     if !path.exists() {
-        println!("image file not found");
+        eprintln!("image file not found");
         return None;
     }
 
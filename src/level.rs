@@ -0,0 +1,87 @@
+use crate::{full_scale, min_max};
+use hound::WavSpec;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Normalization {
+    // every block scaled to its own range
+    Block,
+    // one range for the whole sound
+    File,
+    // largest block range over the last frames
+    Window,
+}
+
+/*
+ * Chooses the range blocks are normalized by, so that quiet passages
+ * can stay quiet, and gates blocks below a threshold to silence.
+ */
+#[derive(Debug, Clone)]
+pub struct Level {
+    mode: Normalization,
+    spec: WavSpec,
+    // range of the whole sound, grows while a stream is read
+    peak: f64,
+    history: VecDeque<f64>,
+    // window length in frames
+    length: usize,
+    // peak amplitude below which a block is silenced, fraction of full scale
+    gate: f64,
+}
+
+impl Level {
+    // samples of the whole sound, empty for streams
+    pub fn new(
+        mode: Normalization,
+        spec: WavSpec,
+        samples: &[i32],
+        length: usize,
+        gate: f64,
+    ) -> Self {
+        let (min, max) = min_max(samples);
+        Self {
+            mode,
+            spec,
+            peak: max as f64 - min as f64,
+            history: VecDeque::new(),
+            length: length.max(1),
+            gate,
+        }
+    }
+
+    // true when the block is quieter than the gate
+    pub fn gated(&self, block: &[i32]) -> bool {
+        let peak = block.iter().map(|s| (*s as f64).abs()).fold(0., f64::max);
+        peak < self.gate * full_scale(&self.spec)
+    }
+
+    /*
+     * Range to divide samples of block by, None to let each block
+     * (and each channel in stereo mode) use its own.
+     * Call once per frame in order.
+     */
+    pub fn range(&mut self, block: &[i32]) -> Option<f64> {
+        let (min, max) = min_max(block);
+        let current = max as f64 - min as f64;
+        let range = match self.mode {
+            Normalization::Block => return None,
+            Normalization::File => {
+                self.peak = self.peak.max(current);
+                self.peak
+            }
+            Normalization::Window => {
+                if self.history.len() == self.length {
+                    self.history.pop_front();
+                }
+                self.history.push_back(current);
+                self.history.iter().copied().fold(0., f64::max)
+            }
+        };
+        // silence so far, same as a silent block
+        Some(if range > 0. {
+            range
+        } else {
+            full_scale(&self.spec)
+        })
+    }
+}
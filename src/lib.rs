@@ -1,8 +1,36 @@
+pub mod analysis;
+pub mod cache;
+pub mod curves;
+pub mod databend;
+pub mod envelope;
+pub mod generator;
+pub mod level;
+pub mod metadata;
+pub mod midi;
+pub mod onset;
+pub mod spectrum;
+pub mod stream;
+pub mod template;
+pub mod tempo;
+pub mod video;
+
 use cairo::ImageSurface;
+use hound::{SampleFormat, WavSpec};
+use image::{DynamicImage, ImageFormat};
+use metadata::Metadata;
 use rustfft::{num_complex::Complex, FftPlanner};
 use std::cmp;
+use std::fs;
 use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Read, Write};
 use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
 
 fn min_max(samples: &[i32]) -> (i32, i32) {
     let min = *samples.iter().min().unwrap_or(&0);
@@ -11,20 +39,38 @@ fn min_max(samples: &[i32]) -> (i32, i32) {
 }
 
 /*
- * Normalize sample slice to f64 -1..1
+ * Magnitude of a full scale sample in the given format.
+ * Float samples are stored scaled to the i32 range.
  */
-pub fn normalize(samples: &[i32]) -> Vec<f64> {
-    let (min, max) = min_max(&samples);
-    if max - min > 0 {
-        samples
-            .iter()
-            .map(|s| *s as f64 / (max - min).abs() as f64)
-            .collect::<Vec<f64>>()
+pub fn full_scale(spec: &WavSpec) -> f64 {
+    match spec.sample_format {
+        SampleFormat::Float => i32::MAX as f64,
+        SampleFormat::Int => (1u64 << (spec.bits_per_sample.clamp(1, 32) - 1)) as f64,
+    }
+}
+
+/*
+ * Range of sample slice, full scale when silent
+ */
+pub fn range(samples: &[i32], spec: &WavSpec) -> f64 {
+    let (min, max) = min_max(samples);
+    if max > min {
+        (max as f64 - min as f64).abs()
     } else {
-        samples.iter().map(|s| *s as f64).collect::<Vec<f64>>()
+        full_scale(spec)
     }
 }
 
+/*
+ * Normalize sample slice to f64 -1..1 by range
+ */
+pub fn normalize(samples: &[i32], range: f64) -> Vec<f64> {
+    samples
+        .iter()
+        .map(|s| *s as f64 / range)
+        .collect::<Vec<f64>>()
+}
+
 pub fn fft(samples: Vec<f64>) -> Vec<Complex<f32>> {
     let size = samples.len();
     let mut planner = FftPlanner::<f32>::new();
@@ -47,59 +93,449 @@ pub fn fft(samples: Vec<f64>) -> Vec<Complex<f32>> {
         .collect::<Vec<Complex<f32>>>()
 }
 
-pub fn rms_16(samples: &[i16]) -> f64 {
+pub fn rms(samples: &[i32], spec: &WavSpec) -> f64 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    let scale = full_scale(spec);
     let squared = samples
         .iter()
-        // i16 because the soundfile is i16. needs to be passed from soundfile info!
-        .map(|s| *s as f64 / i16::MAX as f64)
+        .map(|s| *s as f64 / scale)
         .fold(0.0, |a, s| a + s * s);
     let mean = squared / samples.len() as f64;
     mean.sqrt()
 }
 
-pub fn rms_32(samples: &[i32]) -> f64 {
-    let squared = samples
-        .iter()
-        .map(|s| *s as f64 / i32::MAX as f64)
-        .fold(0.0, |a, s| a + s * s);
-    let mean = squared / samples.len() as f64;
-    mean.sqrt()
+/*
+ * Split interleaved samples into one vector per channel
+ */
+pub fn deinterleave(samples: &[i32], channels: usize) -> Vec<Vec<i32>> {
+    let channels = cmp::max(channels, 1);
+    (0..channels)
+        .map(|c| samples.iter().skip(c).step_by(channels).copied().collect())
+        .collect()
 }
 
 pub fn ramp(size: usize) -> Vec<i32> {
     (0..=size as i32).collect::<Vec<_>>()
 }
 
-pub fn load_soundfile(
-    filename: String,
-    fps: usize,
-    frames: usize,
-    debug: bool,
-) -> (usize, usize, f64, Vec<i32>) {
-    let mut reader = hound::WavReader::open(filename).unwrap();
-    let spec: hound::WavSpec = reader.spec();
-    let duration = reader.duration() as f64;
-    let blocksize: usize = (spec.sample_rate as usize / fps) * spec.channels as usize;
-    let samples: Vec<i32> = reader.samples().map(|s| s.unwrap()).collect();
-    let number_of_frames = if frames > 0 {
-        cmp::min(frames, samples.len() / blocksize)
+/*
+ * Check the RIFF header to tell WAV files from everything else
+ */
+fn is_wav(filename: &str) -> bool {
+    let mut header = [0u8; 12];
+    match File::open(filename).and_then(|mut f| f.read_exact(&mut header)) {
+        Ok(_) => &header[0..4] == b"RIFF" && &header[8..12] == b"WAVE",
+        Err(_) => false,
+    }
+}
+
+// float samples are stored scaled to the i32 range
+pub fn float_to_i32(sample: f32) -> i32 {
+    (sample.clamp(-1., 1.) as f64 * i32::MAX as f64) as i32
+}
+
+/*
+ * Read a WAV file with hound.
+ * Float samples are scaled to the i32 range, integer samples keep their bit depth.
+ */
+fn read_wav(filename: &str) -> (WavSpec, Vec<i32>) {
+    let mut reader = hound::WavReader::open(filename).expect("Could not open sound file");
+    let spec: WavSpec = reader.spec();
+    let samples: Vec<i32> = match spec.sample_format {
+        SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|s| float_to_i32(s.unwrap()))
+            .collect(),
+        SampleFormat::Int => reader.samples::<i32>().map(|s| s.unwrap()).collect(),
+    };
+    (spec, samples)
+}
+
+/*
+ * Decode compressed audio (FLAC, Ogg Vorbis, MP3) to interleaved i32 samples.
+ * The format is probed from the file contents, not the extension.
+ * Lossless integer codecs keep their bit depth, lossy ones are treated like float files.
+ */
+fn decode_soundfile(filename: &str) -> (WavSpec, Vec<i32>) {
+    let file = File::open(filename).expect("Could not open sound file");
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(
+            &Hint::new(),
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .expect("Unsupported sound file format");
+    let mut format = probed.format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .expect("No audio track in sound file");
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+    let mut channels = track.codec_params.channels.map_or(0, |c| c.count());
+    let bits_per_sample = track.codec_params.bits_per_sample.unwrap_or(0);
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .expect("Unsupported codec");
+    let mut samples: Vec<i32> = vec![];
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            // end of stream, other read errors would cut the track short
+            Err(DecodeError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => panic!("Could not read sound file: {}", e),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let spec = *decoded.spec();
+                sample_rate = spec.rate;
+                channels = spec.channels.count();
+                let mut buffer = SampleBuffer::<i32>::new(decoded.capacity() as u64, spec);
+                buffer.copy_interleaved_ref(decoded);
+                samples.extend_from_slice(buffer.samples());
+            }
+            // skip corrupt packets
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(e) => panic!("Could not decode sound file: {}", e),
+        }
+    }
+    let spec = if bits_per_sample > 0 && bits_per_sample < 32 {
+        // SampleBuffer scales to full i32 range, shift back to the native depth
+        let shift = 32 - bits_per_sample;
+        samples.iter_mut().for_each(|s| *s >>= shift);
+        WavSpec {
+            channels: channels as u16,
+            sample_rate,
+            bits_per_sample: bits_per_sample as u16,
+            sample_format: SampleFormat::Int,
+        }
     } else {
-        samples.len() / blocksize
+        WavSpec {
+            channels: channels as u16,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: if bits_per_sample == 32 {
+                SampleFormat::Int
+            } else {
+                SampleFormat::Float
+            },
+        }
     };
+    (spec, samples)
+}
+
+/*
+ * Interleaved samples of a loaded sound file and number of frames to render
+ */
+pub struct Sound {
+    pub frames: usize,
+    pub duration: f64,
+    pub spec: WavSpec,
+    pub samples: Vec<i32>,
+}
+
+/*
+ * Sample range (per channel) of frame i.
+ * Boundaries are rounded from exact timestamps, so frames never drift from the audio.
+ * overlap extends the range past the start of the next frame, as a fraction of a frame.
+ */
+pub fn frame_window(i: usize, sample_rate: u32, fps: usize, overlap: f64) -> (usize, usize) {
+    let hop = sample_rate as f64 / fps as f64;
+    let start = (i as f64 * hop).round() as usize;
+    let end = ((i + 1) as f64 * hop + hop * overlap.max(0.)).round() as usize;
+    (start, end)
+}
+
+impl Sound {
+    // render up to frames frames, 0 for all
+    pub fn new(spec: WavSpec, samples: Vec<i32>, fps: usize, frames: usize) -> Self {
+        let mut sound = Sound {
+            frames: 0,
+            // duration in samples per channel, as reported by hound
+            duration: (samples.len() / cmp::max(spec.channels as usize, 1)) as f64,
+            spec,
+            samples,
+        };
+        let available = sound.frame_count(fps);
+        sound.frames = if frames > 0 {
+            cmp::min(frames, available)
+        } else {
+            available
+        };
+        sound
+    }
+
+    // samples per channel per frame, fractional
+    fn hop(&self, fps: usize) -> f64 {
+        self.spec.sample_rate as f64 / fps as f64
+    }
+
+    fn length(&self) -> usize {
+        self.samples.len() / cmp::max(self.spec.channels as usize, 1)
+    }
+
+    // complete frames of audio
+    pub fn frame_count(&self, fps: usize) -> usize {
+        (self.length() as f64 / self.hop(fps)) as usize
+    }
+
+    // interleaved samples of frame i
+    pub fn block(&self, i: usize, fps: usize, overlap: f64) -> &[i32] {
+        let channels = cmp::max(self.spec.channels as usize, 1);
+        let (start, end) = frame_window(i, self.spec.sample_rate, fps, overlap);
+        let start = cmp::min(start, self.length());
+        let end = cmp::min(end, self.length());
+        &self.samples[start * channels..end * channels]
+    }
+}
+
+pub fn load_soundfile(filename: String, fps: usize, frames: usize, debug: bool) -> Sound {
+    let (spec, samples) = if is_wav(&filename) {
+        read_wav(&filename)
+    } else {
+        decode_soundfile(&filename)
+    };
+    let sound = Sound::new(spec, samples, fps, frames);
 
     if debug {
-        println!("blocksize: {:?}", blocksize);
-        println!("frames: {:?}", number_of_frames);
-        println!("samples: {:?}", samples.len());
+        eprintln!("sample rate: {:?}", spec.sample_rate);
+        eprintln!("channels: {:?}", spec.channels);
+        eprintln!(
+            "format: {:?} {} bit",
+            spec.sample_format, spec.bits_per_sample
+        );
+        eprintln!("samples per frame: {:?}", sound.hop(fps));
+        eprintln!("frames: {:?}", sound.frames);
+        eprintln!("samples: {:?}", sound.samples.len());
     }
 
-    (blocksize, number_of_frames, duration, samples)
+    sound
 }
 
-pub fn save_frame(surface: ImageSurface, outdir: &String, filename: &String) {
-    let path = Path::new(outdir).join(format!("{}.png", filename));
-    let mut outfile = File::create(path).expect("Could not open output file");
-    surface
-        .write_to_png(&mut outfile)
+/*
+ * Image formats of single frames, picked by file extension.
+ * Only PNG keeps the render parameters, PNG and TIFF may have 16 bit channels.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Svg,
+}
+
+impl FrameFormat {
+    pub fn from_extension(extension: &str) -> Result<Self, String> {
+        match extension.to_lowercase().as_str() {
+            "png" => Ok(FrameFormat::Png),
+            "jpg" | "jpeg" => Ok(FrameFormat::Jpeg),
+            "webp" => Ok(FrameFormat::WebP),
+            "tif" | "tiff" => Ok(FrameFormat::Tiff),
+            "svg" => Ok(FrameFormat::Svg),
+            _ => Err(format!(
+                "Could not find an image format for .{}, use png, jpg, webp, tiff or svg",
+                extension
+            )),
+        }
+    }
+
+    /*
+     * Whether a frame file was written to the end. Files are cut short
+     * when a render is interrupted, so look for what is written last.
+     */
+    pub fn is_complete(&self, path: &Path) -> bool {
+        match fs::read(path) {
+            Ok(bytes) => self.is_complete_file(&bytes),
+            Err(_) => false,
+        }
+    }
+
+    fn is_complete_file(&self, bytes: &[u8]) -> bool {
+        match self {
+            // IEND chunk with its CRC
+            FrameFormat::Png => {
+                bytes.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82])
+            }
+            // end of image marker
+            FrameFormat::Jpeg => bytes.ends_with(&[0xff, 0xd9]),
+            // RIFF size after the first 8 bytes
+            FrameFormat::WebP => {
+                bytes.len() >= 8
+                    && u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize + 8
+                        == bytes.len()
+            }
+            // strips are anywhere in the file
+            FrameFormat::Tiff => {
+                image::load_from_memory_with_format(bytes, ImageFormat::Tiff).is_ok()
+            }
+            FrameFormat::Svg => String::from_utf8_lossy(bytes)
+                .trim_end()
+                .ends_with("</svg>"),
+        }
+    }
+
+    // bits per channel the format can be written with
+    pub fn supports_depth(&self, depth: u8) -> bool {
+        match self {
+            FrameFormat::Png | FrameFormat::Tiff => depth == 8 || depth == 16,
+            _ => depth == 8,
+        }
+    }
+}
+
+// raster frame with 8 or 16 bits per channel, PNGs with the render parameters
+pub fn save_frame(
+    mut surface: ImageSurface,
+    path: &Path,
+    format: FrameFormat,
+    depth: u8,
+    metadata: &Metadata,
+) {
+    let image = DynamicImage::ImageRgba8(video::rgba(&mut surface));
+    match format {
+        FrameFormat::Png => save_png(&image, path, depth, metadata),
+        FrameFormat::Jpeg => image
+            .to_rgb8()
+            .save_with_format(path, ImageFormat::Jpeg)
+            .expect("Could not write to output file"),
+        FrameFormat::WebP => image
+            .save_with_format(path, ImageFormat::WebP)
+            .expect("Could not write to output file"),
+        FrameFormat::Tiff if depth == 16 => image
+            .to_rgba16()
+            .save_with_format(path, ImageFormat::Tiff)
+            .expect("Could not write to output file"),
+        FrameFormat::Tiff => image
+            .save_with_format(path, ImageFormat::Tiff)
+            .expect("Could not write to output file"),
+        FrameFormat::Svg => panic!("Could not write a raster frame as SVG"),
+    }
+}
+
+fn save_png(image: &DynamicImage, path: &Path, depth: u8, metadata: &Metadata) {
+    let outfile = BufWriter::new(File::create(path).expect("Could not open output file"));
+    write_png(outfile, image, depth, metadata);
+}
+
+// PNG with the render parameters in text chunks
+pub fn write_png<W: Write>(writer: W, image: &DynamicImage, depth: u8, metadata: &Metadata) {
+    let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    let data = if depth == 16 {
+        encoder.set_depth(png::BitDepth::Sixteen);
+        // big endian samples
+        image
+            .to_rgba16()
+            .iter()
+            .flat_map(|sample| sample.to_be_bytes())
+            .collect()
+    } else {
+        encoder.set_depth(png::BitDepth::Eight);
+        image.to_rgba8().into_raw()
+    };
+    let (latin1, utf8) = metadata.chunks();
+    for (keyword, text) in latin1 {
+        encoder.add_text_chunk(keyword, text).unwrap();
+    }
+    for (keyword, text) in utf8 {
+        encoder.add_itxt_chunk(keyword, text).unwrap();
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
         .expect("Could not write to output file");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a small frame encoded as format, and cut short
+    fn frame_file(format: FrameFormat) -> (Vec<u8>, Vec<u8>) {
+        let image = DynamicImage::ImageRgba8(image::RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([x as u8 * 16, y as u8 * 16, 128, 255])
+        }));
+        let mut bytes = std::io::Cursor::new(vec![]);
+        let (bytes, cut) = match format {
+            FrameFormat::Png => {
+                write_png(&mut bytes, &image, 8, &Metadata::default());
+                (bytes.into_inner(), 4)
+            }
+            FrameFormat::Jpeg => {
+                image
+                    .to_rgb8()
+                    .write_to(&mut bytes, ImageFormat::Jpeg)
+                    .unwrap();
+                // the end of image marker
+                (bytes.into_inner(), 2)
+            }
+            FrameFormat::WebP => {
+                image.write_to(&mut bytes, ImageFormat::WebP).unwrap();
+                (bytes.into_inner(), 1)
+            }
+            FrameFormat::Tiff => {
+                image.write_to(&mut bytes, ImageFormat::Tiff).unwrap();
+                let length = bytes.get_ref().len();
+                (bytes.into_inner(), length / 2)
+            }
+            FrameFormat::Svg => {
+                let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\"><path d=\"M0 0\"/></svg>\n";
+                (svg.to_vec(), 8)
+            }
+        };
+        let short = bytes[..bytes.len() - cut].to_vec();
+        (bytes, short)
+    }
+
+    #[test]
+    fn complete_and_truncated_frames() {
+        for format in [
+            FrameFormat::Png,
+            FrameFormat::Jpeg,
+            FrameFormat::WebP,
+            FrameFormat::Tiff,
+            FrameFormat::Svg,
+        ] {
+            let (complete, truncated) = frame_file(format);
+            assert!(format.is_complete_file(&complete), "{:?}", format);
+            assert!(!format.is_complete_file(&truncated), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn frame_window_30_fps() {
+        assert_eq!(frame_window(0, 44100, 30, 0.), (0, 1470));
+        assert_eq!(frame_window(1, 44100, 30, 0.), (1470, 2940));
+        assert_eq!(frame_window(1, 44100, 30, 0.5), (1470, 3675));
+    }
+
+    #[test]
+    fn frame_window_24_fps() {
+        // 1837.5 samples per frame, alternately rounded down and up
+        assert_eq!(frame_window(0, 44100, 24, 0.), (0, 1838));
+        assert_eq!(frame_window(1, 44100, 24, 0.), (1838, 3675));
+        assert_eq!(frame_window(2, 44100, 24, 0.), (3675, 5513));
+    }
+
+    #[test]
+    fn frame_window_does_not_drift() {
+        for fps in [24, 25, 30, 60] {
+            for i in 0..100_000 {
+                let (start, end) = frame_window(i, 44100, fps, 0.);
+                // exact timestamp rounded half up
+                assert_eq!(start, (i * 44100 + fps / 2) / fps);
+                assert_eq!(end, frame_window(i + 1, 44100, fps, 0.).0);
+            }
+        }
+    }
+}
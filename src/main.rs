@@ -5,138 +5,175 @@ extern crate image;
 
 mod args;
 mod feed;
+mod frames;
 mod ghostweb;
+mod output;
 mod render;
+mod timeline;
 
 use args::Args;
+use args::Command;
 use args::Method;
-use cairo::{Context, Format, ImageSurface};
+use cairo::Context;
 use clap::Parser;
-use degenerate::{load_soundfile, ramp, save_frame};
-use ghostweb::{ghostweb, load_image};
+use degenerate::cache::{FeatureCache, SoundCache};
+use degenerate::metadata::{hash_file, Metadata};
+use degenerate::{save_frame, FrameFormat};
+use frames::{Frame, Frames};
+use ghostweb::ghostweb;
+use output::{raster, Output};
 use pbr::ProgressBar;
-use std::convert::TryInto;
-
-macro_rules! validate {
-    ($e:expr, $msg:expr) => {
-        if !$e {
-            return Err(Error::BadRequest($msg.into()));
-        }
-    };
-}
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::Stderr;
+use std::path::Path;
 
 fn main() {
-    let args = Args::parse();
-    let radius = if args.radius > 0. {
-        args.radius
-    } else {
-        (args.width / 2) as f64
-    };
-    multi_frame(radius, args)
+    let argv: Vec<String> = env::args().collect();
+    let args = Args::parse_from(&argv);
+    match &args.command {
+        Some(Command::Analyze { output }) => analyze(&args, output),
+        Some(Command::Rerender { image, output }) => rerender(image, output.as_deref()),
+        None => multi_frame(&args, &args::resolve(&argv)),
+    }
 }
 
-fn multi_frame(radius: f64, args: Args) {
-    let frames: usize;
-    let duration: f64;
-    let blocksize: usize;
-    let samples: Vec<i32>;
-    let mut radius = radius;
-    let image = if args.image.is_empty() {
-        None
-    } else {
-        load_image(&args.image, args.scale_image)
-    };
-    let (is, xs): (u32, Vec<ghostweb::Feed>) = match image {
-        None => (0, vec![]),
-        Some((is, xs)) => (is, xs),
-    };
-
-    let iterations = if args.iterations > 0 {
-        args.iterations
-    } else {
-        match is {
-            0 => args.width * args.height,
-            _ => is,
-        }
-    };
+// progress goes to stderr, stdout may carry video
+fn progress(frames: &Frames) -> ProgressBar<Stderr> {
+    let mut pb = ProgressBar::on(io::stderr(), frames.source.frames as u64);
+    if frames.source.is_open() {
+        // until the stream ends
+        pb.show_bar = false;
+        pb.show_percent = false;
+        pb.show_time_left = false;
+    }
+    pb
+}
 
-    if args.soundfile.is_empty() {
-        blocksize = 255;
-        frames = if args.frames > 0 { args.frames } else { 1 };
-        duration = frames as f64 / args.fps as f64;
-        samples = ramp(blocksize * frames);
-    } else {
-        // the compiler doesn't like destructuring assignment
-        let result = load_soundfile(args.soundfile.clone(), args.fps, args.frames, args.debug);
-        blocksize = result.0;
-        frames = result.1;
-        duration = result.2;
-        samples = result.3;
+// argv is stored with every PNG, resolved to render the same from anywhere
+fn multi_frame(args: &Args, argv: &[String]) {
+    let mut frames = Frames::new(args);
+    let count = (!frames.source.is_open()).then_some(frames.source.frames);
+    let mut output = Output::create(args, count);
+    let mut pb = progress(&frames);
+    for i in frames.source.range() {
+        let frame = match frames.resolve(i) {
+            Some(frame) => frame,
+            None => break,
+        };
+        let metadata = frame_metadata(argv, &frame, &frames.source.hash);
+        output.write(&frame, &metadata, |context| {
+            render(context, &frame, &frames.xs, args.debug)
+        });
+        pb.inc();
     }
-    let mut block_iterator = samples.chunks(blocksize).skip(args.start);
-
-    let basename = args.filename.clone();
-    let outdir = args.outdir.clone();
-    let mut pb = ProgressBar::new(frames as u64);
-    let end = args.start + frames;
-
-    for i in args.start..end {
-        let block: Vec<i32>;
-        let t = i as f64 / duration as f64 * args.t;
-        let filename = format!("{}{}", basename, format!("{:01$}", i, 6));
-        radius = radius * args.expansion;
-
-        block = block_iterator
-            .next()
-            .unwrap()
-            .try_into()
-            .expect("could not unwrap soundfile sample block");
-
-        let config =
-            render::RenderConfig::new(iterations, args.method.clone(), radius, block, t, &args);
-        let frame = match xs[..] {
-            [] => render_frame(config, args.debug),
-            _ => render_displacement_frame(config, &xs, i as f64 / frames as f64, args.debug),
+    output.finish();
+    pb.finish_print("done!");
+}
+
+// per-frame features of the sound to CSV, nothing is rendered
+fn analyze(args: &Args, filename: &str) {
+    let mut frames = Frames::new(args);
+    let mut analysis = FeatureCache::default();
+    let mut pb = progress(&frames);
+    for i in frames.source.range() {
+        let features = match frames.analyze(i) {
+            Some((_, _, features)) => features,
+            None => break,
         };
-        save_frame(frame, &outdir, &filename);
+        analysis.push(i, i as f64 / args.fps as f64, features);
         pb.inc();
     }
+    analysis.save(filename);
+    if let Some(sound) = frames.source.into_cache(args) {
+        sound.save(&SoundCache::path(filename));
+    }
     pb.finish_print("done!");
 }
 
-fn render_frame(conf: render::RenderConfig, debug: bool) -> ImageSurface {
-    let surface =
-        ImageSurface::create(Format::ARgb32, conf.width as i32, conf.height as i32).unwrap();
-    let context = Context::new(&surface).unwrap();
-    let xs = ghostweb(
-        conf.iterations,
-        &conf.block,
-        conf.radius,
-        conf.f1,
-        conf.f2,
-        conf.m,
-        conf.t,
-    );
-    draw_frame(
-        &context,
-        &xs,
-        conf.width,
-        conf.height,
-        conf.size,
-        debug,
-        &conf.method,
-        conf.combine_dots,
-    );
-    surface
+/*
+ * Render one frame of a PNG again from its embedded parameters.
+ * The frames before it are resolved, not drawn, since growth, envelopes
+ * and onsets carry state from frame to frame.
+ */
+fn rerender(image: &str, output: Option<&str>) {
+    let file = File::open(image).expect("Could not open image file");
+    let expected = Metadata::read(BufReader::new(file), image);
+    let output = match output {
+        Some(output) => Path::new(output).to_path_buf(),
+        None => Path::new(image).with_extension("rerender.png"),
+    };
+    let args = Args::parse_from(&expected.args);
+    let version = env!("CARGO_PKG_VERSION");
+    if expected.version != version {
+        eprintln!(
+            "{} was rendered by degenerate {}, this is {}, the frame may differ",
+            image, expected.version, version
+        );
+    }
+    if !expected.source.is_empty() && hash_file(&args.soundfile) != expected.source {
+        eprintln!("{} changed since the frame was rendered", args.soundfile);
+    }
+    let mut frames = Frames::new(&args);
+    let mut frame = None;
+    for i in frames.source.start..=expected.frame {
+        frame = frames.resolve(i);
+    }
+    let frame = frame.unwrap_or_else(|| panic!("Could not reach frame {}", expected.frame));
+    let metadata = frame_metadata(&expected.args, &frame, &frames.source.hash);
+    if (metadata.t, metadata.radius, metadata.f1, metadata.f2)
+        != (expected.t, expected.radius, expected.f1, expected.f2)
+    {
+        eprintln!("parameters differ from the original frame: {:?}", metadata);
+    }
+    let surface = raster(&frame, |context| {
+        render(context, &frame, &frames.xs, args.debug)
+    });
+    save_frame(surface, &output, FrameFormat::Png, args.depth, &metadata);
+}
+
+fn frame_metadata(argv: &[String], frame: &Frame, source: &str) -> Metadata {
+    Metadata {
+        args: argv.to_vec(),
+        frame: frame.index,
+        t: frame.config.t,
+        radius: frame.config.radius,
+        f1: frame.config.f1,
+        f2: frame.config.f2,
+        source: source.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
+
+fn render(context: &Context, frame: &Frame, xs: &[ghostweb::Feed], debug: bool) {
+    match xs[..] {
+        [] => render_frame(context, &frame.config, debug),
+        _ => render_displacement_frame(
+            context,
+            &frame.config,
+            xs,
+            frame.scale_image,
+            frame.strength,
+            debug,
+        ),
+    }
+}
+
+fn render_frame(context: &Context, conf: &render::RenderConfig, debug: bool) {
+    let xs = ghostweb(conf);
+    draw_frame(context, &xs, conf, debug);
 }
 
 fn displace(
-    pixels: &Vec<ghostweb::Feed>,
-    dx: &Vec<ghostweb::Feed>,
+    pixels: &[ghostweb::Feed],
+    dx: &[ghostweb::Feed],
+    scale: f64,
     strength: f64,
 ) -> Vec<ghostweb::Feed> {
     pixels
-        .into_iter()
+        .iter()
         .zip(dx)
         .map(|(p, x)| ghostweb::Feed {
             p1: ghostweb::Point {
@@ -149,62 +186,40 @@ fn displace(
                 y: p.p1.y * (1. - strength) + x.p2.y * strength,
                 z: p.p1.z * (1. - strength) + x.p2.z * strength,
             },
-            radius: p.radius * (1. - strength) + x.radius * strength,
+            radius: p.radius * scale * (1. - strength) + x.radius * strength,
         })
         .collect()
 }
 
 fn render_displacement_frame(
-    conf: render::RenderConfig,
-    pixels: &Vec<ghostweb::Feed>,
+    context: &Context,
+    conf: &render::RenderConfig,
+    pixels: &[ghostweb::Feed],
+    scale: f64,
     strength: f64,
     debug: bool,
-) -> ImageSurface {
-    let surface =
-        ImageSurface::create(Format::ARgb32, conf.width as i32, conf.height as i32).unwrap();
-    let context = Context::new(&surface).unwrap();
-    let xs = ghostweb(
-        conf.iterations,
-        &conf.block,
-        conf.radius,
-        conf.f1,
-        conf.f2,
-        conf.m,
-        conf.t,
-    );
+) {
+    let xs = ghostweb(conf);
     draw_frame(
-        &context,
-        &displace(&pixels, &xs, strength),
-        conf.width,
-        conf.height,
-        conf.size,
+        context,
+        &displace(pixels, &xs, scale, strength),
+        conf,
         debug,
-        &conf.method,
-        conf.combine_dots,
     );
-    surface
 }
 
-fn draw_frame(
-    context: &Context,
-    xs: &Vec<ghostweb::Feed>,
-    width: u32,
-    height: u32,
-    size: f64,
-    debug: bool,
-    method: &Method,
-    combine_dots: bool,
-) {
-    let cx: f64 = width as f64 / 2.;
-    let cy: f64 = height as f64 / 2.;
+fn draw_frame(context: &Context, xs: &[ghostweb::Feed], conf: &render::RenderConfig, debug: bool) {
+    let cx: f64 = conf.width as f64 / 2.;
+    let cy: f64 = conf.height as f64 / 2.;
+    let size = conf.size;
 
-    // black out
-    context.set_source_rgb(0.0, 0.0, 0.0);
+    // black out, or flash on onsets
+    context.set_source_rgb(conf.flash, conf.flash, conf.flash);
     context.paint().unwrap();
 
     for x in xs {
         if debug {
-            println!("{:?}", x);
+            eprintln!("{:?}", x);
         }
 
         let crx1 = cx + x.p1.x * x.radius;
@@ -218,7 +233,7 @@ fn draw_frame(
         context.set_source_rgba(1.0, 1.0, 1.0, 1.0);
         context.move_to(crx1, cry1);
 
-        match method {
+        match conf.method {
             Method::Arc => context.arc(crx1, cry1, x.radius, x.p1.z, x.p2.z),
             Method::Curve => context.curve_to(
                 crx1,
@@ -230,11 +245,19 @@ fn draw_frame(
             ),
             Method::Dot => {
                 context.set_source_rgba(1.0, 1.0, 1.0, 1.0);
-                if combine_dots {
+                if conf.combine_dots {
                     context.rectangle(crx3, cry3, 0.5, 0.5);
                 } else {
-                    let size_1 = if size > 0. { x.p1.z.abs() * size } else { 1.0 };
-                    let size_2 = if size > 0. { x.p2.z.abs() * size } else { 1.0 };
+                    let size_1 = if size > 0. {
+                        x.p1.z.abs() * size
+                    } else {
+                        conf.dot
+                    };
+                    let size_2 = if size > 0. {
+                        x.p2.z.abs() * size
+                    } else {
+                        conf.dot
+                    };
                     context.rectangle(crx1, cry1, size_1, size_1);
                     context.stroke().unwrap();
                     context.fill().unwrap();
@@ -0,0 +1,148 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+
+const ARGS: &str = "degenerate:args";
+const FRAME: &str = "degenerate:frame";
+const T: &str = "degenerate:t";
+const RADIUS: &str = "degenerate:radius";
+const EQUATIONS: &str = "degenerate:equations";
+const SOURCE: &str = "degenerate:source";
+const VERSION: &str = "Software";
+
+// keyword and text
+type Chunks = Vec<(String, String)>;
+
+/*
+ * Render parameters of a frame, kept in PNG text chunks.
+ * The command line is enough to render the frame again, the rest is
+ * there to check the result and to read without the program.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    // command line with all options and absolute input paths, program name first
+    pub args: Vec<String>,
+    pub frame: usize,
+    pub t: f64,
+    pub radius: f64,
+    pub f1: usize,
+    pub f2: usize,
+    // sha256 of the sound file, empty without one
+    pub source: String,
+    pub version: String,
+}
+
+impl Metadata {
+    // latin1 and utf8 chunks, the command line may hold any unicode
+    pub fn chunks(&self) -> (Chunks, Chunks) {
+        let latin1 = vec![
+            (FRAME, self.frame.to_string()),
+            (T, self.t.to_string()),
+            (RADIUS, self.radius.to_string()),
+            (EQUATIONS, format!("{} {}", self.f1, self.f2)),
+            (SOURCE, self.source.clone()),
+            (VERSION, format!("degenerate {}", self.version)),
+        ];
+        let utf8 = vec![(ARGS, serde_json::to_string(&self.args).unwrap())];
+        let owned = |chunks: Vec<(&str, String)>| {
+            chunks
+                .into_iter()
+                .map(|(keyword, text)| (keyword.to_string(), text))
+                .collect()
+        };
+        (owned(latin1), owned(utf8))
+    }
+
+    // name of the image for messages
+    pub fn read(image: impl Read, name: &str) -> Self {
+        let decoder = png::Decoder::new(image);
+        let reader = decoder.read_info().expect("Could not read PNG");
+        let info = reader.info();
+        let mut chunks: Chunks = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+            .collect();
+        chunks.extend(
+            info.utf8_text
+                .iter()
+                .filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?))),
+        );
+        let text = |keyword: &str| {
+            chunks
+                .iter()
+                .find(|(k, _)| k == keyword)
+                .map(|(_, text)| text.clone())
+                .unwrap_or_default()
+        };
+        let number = |keyword: &str| {
+            text(keyword)
+                .parse::<f64>()
+                .unwrap_or_else(|_| panic!("Could not read {} from {}", keyword, name))
+        };
+        let equations = text(EQUATIONS)
+            .split(' ')
+            .map(|index| index.parse::<usize>().unwrap_or_default())
+            .collect::<Vec<usize>>();
+
+        Self {
+            args: serde_json::from_str(&text(ARGS))
+                .unwrap_or_else(|_| panic!("No render parameters in {}", name)),
+            frame: number(FRAME) as usize,
+            t: number(T),
+            radius: number(RADIUS),
+            f1: equations.first().copied().unwrap_or_default(),
+            f2: equations.get(1).copied().unwrap_or_default(),
+            source: text(SOURCE),
+            version: text(VERSION).trim_start_matches("degenerate ").to_string(),
+        }
+    }
+}
+
+// sha256 of a file as hex
+pub fn hash_file(filename: &str) -> String {
+    let bytes = fs::read(filename).expect("Could not open file");
+    Sha256::digest(&bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write_png;
+    use image::{DynamicImage, RgbaImage};
+
+    #[test]
+    fn round_trip() {
+        let metadata = Metadata {
+            args: vec![
+                "degenerate".to_string(),
+                "--filename=ünï_{frame}.png".to_string(),
+                "--".to_string(),
+                "/sounds/a b.wav".to_string(),
+            ],
+            frame: 42,
+            t: 0.123456789,
+            radius: 1234.5,
+            f1: 3,
+            f2: 17,
+            source: "ab".repeat(32),
+            version: "1.2.3".to_string(),
+        };
+        for depth in [8, 16] {
+            let image = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+            let mut png = vec![];
+            write_png(&mut png, &image, depth, &metadata);
+            let read = Metadata::read(png.as_slice(), "test");
+            assert_eq!(read.args, metadata.args);
+            assert_eq!(read.frame, metadata.frame);
+            assert_eq!(read.t, metadata.t);
+            assert_eq!(read.radius, metadata.radius);
+            assert_eq!((read.f1, read.f2), (metadata.f1, metadata.f2));
+            assert_eq!(read.source, metadata.source);
+            assert_eq!(read.version, metadata.version);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::fs;
+
+// microseconds per beat until the first tempo event
+const DEFAULT_TEMPO: f64 = 500_000.;
+
+#[derive(Debug, Clone, Copy)]
+enum Message {
+    NoteOn { key: u8, velocity: u8 },
+    NoteOff { key: u8 },
+    Controller { controller: u8, value: u8 },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Event {
+    // seconds
+    time: f64,
+    message: Message,
+}
+
+// what a binding reads from the MIDI state
+#[derive(Debug, Clone, Copy)]
+pub enum MidiSource {
+    Note,
+    Velocity,
+    Controller(u8),
+}
+
+/*
+ * Note and controller state at one point in time.
+ * Values are 7 bit MIDI numbers.
+ */
+#[derive(Debug, Clone)]
+pub struct MidiState {
+    // most recent held note and its velocity
+    pub note: Option<(u8, u8)>,
+    pub controllers: [u8; 128],
+    // a note started since the previous frame
+    pub onset: bool,
+}
+
+impl MidiState {
+    pub fn raw(&self, source: MidiSource) -> u8 {
+        match source {
+            MidiSource::Note => self.note.map_or(0, |(key, _)| key),
+            MidiSource::Velocity => self.note.map_or(0, |(_, velocity)| velocity),
+            MidiSource::Controller(controller) => self.controllers[controller as usize & 127],
+        }
+    }
+
+    // 0..1
+    pub fn value(&self, source: MidiSource) -> f64 {
+        self.raw(source) as f64 / 127.
+    }
+}
+
+/*
+ * Standard MIDI file flattened to timed events of all tracks and channels,
+ * played back frame by frame.
+ */
+pub struct Midi {
+    events: Vec<Event>,
+    pub duration: f64,
+    cursor: usize,
+    // time of the last advance
+    time: f64,
+    held: Vec<(u8, u8)>,
+    controllers: [u8; 128],
+}
+
+impl Midi {
+    pub fn load(filename: &str) -> Self {
+        let bytes = fs::read(filename).expect("Could not open MIDI file");
+        let smf = Smf::parse(&bytes).expect("Could not parse MIDI file");
+
+        // absolute ticks, tempo changes apply across tracks
+        let mut ticks: Vec<(u64, TrackEventKind)> = vec![];
+        for track in smf.tracks.iter() {
+            let mut tick = 0u64;
+            for event in track.iter() {
+                tick += event.delta.as_int() as u64;
+                ticks.push((tick, event.kind));
+            }
+        }
+        ticks.sort_by_key(|(tick, _)| *tick);
+
+        let mut events = vec![];
+        let mut tempo = DEFAULT_TEMPO;
+        let mut time = 0.;
+        let mut last = 0u64;
+        for (tick, kind) in ticks {
+            time += match smf.header.timing {
+                Timing::Metrical(ppq) => {
+                    (tick - last) as f64 * tempo / (ppq.as_int() as f64 * 1_000_000.)
+                }
+                Timing::Timecode(fps, subframes) => {
+                    (tick - last) as f64 / (fps.as_f32() as f64 * subframes as f64)
+                }
+            };
+            last = tick;
+
+            let message = match kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(t)) => {
+                    tempo = t.as_int() as f64;
+                    None
+                }
+                TrackEventKind::Midi { message, .. } => match message {
+                    // by convention a note on with zero velocity is a note off
+                    MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => Some(Message::NoteOn {
+                        key: key.as_int(),
+                        velocity: vel.as_int(),
+                    }),
+                    MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                        Some(Message::NoteOff { key: key.as_int() })
+                    }
+                    MidiMessage::Controller { controller, value } => Some(Message::Controller {
+                        controller: controller.as_int(),
+                        value: value.as_int(),
+                    }),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some(message) = message {
+                events.push(Event { time, message });
+            }
+        }
+
+        Self {
+            events,
+            duration: time,
+            cursor: 0,
+            time: 0.,
+            held: vec![],
+            controllers: [0; 128],
+        }
+    }
+
+    /*
+     * State after all events up to time.
+     * Going back in time, as loops do, replays the events from the start,
+     * notes passed on the way back count as onsets too.
+     */
+    pub fn advance(&mut self, time: f64) -> MidiState {
+        let mut onset = false;
+        let back = time < self.time;
+        if back {
+            onset = self.events[..self.cursor]
+                .iter()
+                .any(|event| event.time > time && matches!(event.message, Message::NoteOn { .. }));
+            self.cursor = 0;
+            self.held.clear();
+            self.controllers = [0; 128];
+        }
+        self.time = time;
+        while self.cursor < self.events.len() && self.events[self.cursor].time <= time {
+            match self.events[self.cursor].message {
+                Message::NoteOn { key, velocity } => {
+                    self.held.retain(|(k, _)| *k != key);
+                    self.held.push((key, velocity));
+                    // replayed notes were passed before
+                    onset |= !back;
+                }
+                Message::NoteOff { key } => self.held.retain(|(k, _)| *k != key),
+                Message::Controller { controller, value } => {
+                    self.controllers[controller as usize] = value
+                }
+            }
+            self.cursor += 1;
+        }
+        MidiState {
+            note: self.held.last().copied(),
+            controllers: self.controllers,
+            onset,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+// frames of novelty the threshold adapts to
+const HISTORY: usize = 16;
+// frames to wait after an onset before the next one
+const MIN_GAP: usize = 3;
+// ignore novelty below this, silence is not a transient
+const FLOOR: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy)]
+pub enum OnsetMode {
+    // rising spectral energy, catches hits within sustained sounds
+    Flux,
+    // rising rms, cheaper and fine for percussive material
+    Energy,
+}
+
+/*
+ * Causal peak picker over a novelty curve.
+ * A frame is an onset when its novelty exceeds the recent mean by the
+ * threshold factor and the last onset is at least MIN_GAP frames back.
+ * Neither the first frame nor the one after it, without history, are onsets.
+ */
+pub struct OnsetDetector {
+    mode: OnsetMode,
+    threshold: f64,
+    history: VecDeque<f64>,
+    // none before the first frame
+    previous_rms: Option<f64>,
+    since: usize,
+}
+
+impl OnsetDetector {
+    pub fn new(mode: OnsetMode, threshold: f64) -> Self {
+        Self {
+            mode,
+            threshold,
+            history: VecDeque::with_capacity(HISTORY),
+            previous_rms: None,
+            since: MIN_GAP,
+        }
+    }
+
+    // none for the first frame, its flux is against silence
+    pub fn novelty(&mut self, flux: f64, rms: f64) -> Option<f64> {
+        let novelty = match (self.mode, self.previous_rms) {
+            (_, None) => None,
+            (OnsetMode::Flux, _) => Some(flux),
+            (OnsetMode::Energy, Some(previous)) => Some((rms - previous).max(0.)),
+        };
+        self.previous_rms = Some(rms);
+        novelty
+    }
+
+    pub fn detect(&mut self, novelty: f64) -> bool {
+        let onset = !self.history.is_empty()
+            && novelty > FLOOR
+            && novelty
+                > self.history.iter().sum::<f64>() / self.history.len() as f64 * self.threshold
+            && self.since >= MIN_GAP;
+
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(novelty);
+        self.since = if onset { 0 } else { self.since + 1 };
+        onset
+    }
+}
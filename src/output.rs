@@ -0,0 +1,169 @@
+use super::args::{Args, Existing};
+use super::frames::Frame;
+use cairo::{Context, Format, ImageSurface, PdfSurface, SvgSurface};
+use degenerate::metadata::Metadata;
+use degenerate::template::{Template, Value};
+use degenerate::video::Video;
+use degenerate::{save_frame, FrameFormat};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// frame drawn into a new image surface
+pub fn raster(frame: &Frame, draw: impl FnOnce(&Context)) -> ImageSurface {
+    let (width, height) = (frame.config.width as i32, frame.config.height as i32);
+    let surface = ImageSurface::create(Format::ARgb32, width, height).unwrap();
+    draw(&Context::new(&surface).unwrap());
+    surface
+}
+
+/*
+ * Where rendered frames go: pages of one PDF, one video or animation file,
+ * or a file per frame named by the filename template.
+ */
+pub enum Output {
+    Pdf(PdfSurface),
+    Video(Video),
+    Files {
+        outdir: String,
+        filename: Template,
+        format: FrameFormat,
+        depth: u8,
+        existing: Existing,
+        // finished frames kept
+        skipped: usize,
+    },
+}
+
+impl Output {
+    // frames is the frame count, None for streams read until they end
+    pub fn create(args: &Args, frames: Option<usize>) -> Self {
+        if (!args.pdf.is_empty() || !args.video.is_empty())
+            && (args.resume || args.overwrite != Existing::Overwrite)
+        {
+            panic!("Could not apply --resume or --overwrite to a PDF or video file");
+        }
+        if !args.pdf.is_empty() {
+            return Output::Pdf(
+                PdfSurface::new(args.width as f64, args.height as f64, &args.pdf)
+                    .expect("Could not open PDF file"),
+            );
+        }
+        if !args.video.is_empty() {
+            return Output::Video(Video::create(
+                &args.video,
+                args.width,
+                args.height,
+                args.fps,
+                frames,
+                args.dither,
+            ));
+        }
+        let format = if args.svg {
+            FrameFormat::Svg
+        } else {
+            FrameFormat::from_extension(&args.filename.extension())
+                .unwrap_or_else(|e| panic!("{}", e))
+        };
+        if !format.supports_depth(args.depth) {
+            panic!(
+                "Could not write {:?} frames with {} bit",
+                format, args.depth
+            );
+        }
+        Output::Files {
+            outdir: args.outdir.clone(),
+            filename: args.filename.clone(),
+            format,
+            depth: args.depth,
+            existing: if args.resume {
+                Existing::Skip
+            } else {
+                args.overwrite
+            },
+            skipped: 0,
+        }
+    }
+
+    // draw a frame into the output, frames kept by the overwrite policy are not drawn
+    pub fn write(&mut self, frame: &Frame, metadata: &Metadata, draw: impl FnOnce(&Context)) {
+        match self {
+            Output::Pdf(pdf) => {
+                // one page per frame
+                let context = Context::new(&*pdf).unwrap();
+                draw(&context);
+                context.show_page().expect("Could not write PDF page");
+            }
+            Output::Video(video) => video.write_frame(raster(frame, draw)),
+            Output::Files {
+                outdir,
+                filename,
+                format,
+                depth,
+                existing,
+                skipped,
+            } => {
+                let path = frame_path(outdir, filename, *format, frame);
+                if path.exists() {
+                    match existing {
+                        Existing::Skip if format.is_complete(&path) => {
+                            *skipped += 1;
+                            return;
+                        }
+                        Existing::Skip => {
+                            eprintln!("{} is incomplete, rendering again", path.display())
+                        }
+                        Existing::Overwrite => {}
+                        Existing::Fail => panic!("{} exists", path.display()),
+                    }
+                }
+                if *format == FrameFormat::Svg {
+                    let (width, height) = (frame.config.width as f64, frame.config.height as f64);
+                    let surface = SvgSurface::new(width, height, Some(path))
+                        .expect("Could not open output file");
+                    draw(&Context::new(&surface).unwrap());
+                    surface.finish();
+                } else {
+                    save_frame(raster(frame, draw), &path, *format, *depth, metadata);
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) {
+        match self {
+            Output::Pdf(pdf) => pdf.finish(),
+            Output::Video(video) => video.finish(),
+            Output::Files { skipped, .. } if skipped > 0 => {
+                eprintln!("skipped {} finished frames", skipped)
+            }
+            Output::Files { .. } => {}
+        }
+    }
+}
+
+// file of a frame, directories by parameter are created
+fn frame_path(outdir: &str, filename: &Template, format: FrameFormat, frame: &Frame) -> PathBuf {
+    let config = &frame.config;
+    let filename = filename.format(|name| match name {
+        "frame" => Value::Integer(frame.index),
+        "time" => Value::Number(frame.time),
+        "t" => Value::Number(config.t),
+        "radius" => Value::Number(config.radius),
+        "m" => Value::Number(config.m),
+        "size" => Value::Number(config.size),
+        "iterations" => Value::Integer(config.iterations as usize),
+        "flash" => Value::Number(config.flash),
+        "f1" => Value::Integer(config.f1),
+        "f2" => Value::Integer(config.f2),
+        "method" => Value::Text(format!("{:?}", config.method).to_lowercase()),
+        _ => unreachable!("checked on parse"),
+    });
+    let path = Path::new(outdir).join(filename);
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).expect("Could not create output directory");
+    }
+    match format {
+        FrameFormat::Svg => path.with_extension("svg"),
+        _ => path,
+    }
+}
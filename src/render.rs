@@ -1,4 +1,16 @@
-use super::args::{Args, Method};
+use super::args::{Args, Method, Target};
+use super::ghostweb::EQUATIONS;
+use degenerate::analysis::Features;
+use degenerate::spectrum::Stft;
+use hound::WavSpec;
+
+// position in a seamless loop and the span t swings through
+#[derive(Debug, Clone, Copy)]
+pub struct Cycle {
+    // 0..1
+    pub phase: f64,
+    pub span: f64,
+}
 
 #[derive(Debug)]
 pub struct RenderConfig {
@@ -13,19 +25,38 @@ pub struct RenderConfig {
     pub f1: usize,
     pub f2: usize,
     pub block: Vec<i32>,
+    // channels, bit depth and sample format of block
+    pub spec: WavSpec,
+    // samples are divided by range, None for each block's own
+    pub range: Option<f64>,
+    // analyse left and right channel separately
+    pub stereo: bool,
+    // windowed analysis instead of one FFT per block
+    pub stft: Option<Stft>,
+    // rms and spectral descriptors of block
+    pub features: Features,
     pub width: u32,
     pub height: u32,
     pub method: Method,
     pub size: f64,
+    // dot size while size is 0
+    pub dot: f64,
     pub combine_dots: bool,
+    // background brightness, 0 is black
+    pub flash: f64,
+    // position in current beat and bar, 0..1
+    pub beat: f64,
+    pub bar: f64,
+    pub cycle: Option<Cycle>,
 }
 
 impl RenderConfig {
     pub fn new(
         iterations: u32,
-        method: Method,
         radius: f64,
         block: Vec<i32>,
+        spec: WavSpec,
+        features: Features,
         t: f64,
         args: &Args,
     ) -> Self {
@@ -37,11 +68,54 @@ impl RenderConfig {
             f1: args.f1,
             f2: args.f2,
             block,
+            spec,
+            range: None,
+            stereo: args.stereo,
+            stft: if args.window_size > 0 {
+                Some(Stft::new(args.window, args.window_size, args.hop))
+            } else {
+                None
+            },
+            features,
             width: args.width,
             height: args.height,
-            method,
+            method: args.method.clone(),
             size: args.size,
+            dot: 1.,
             combine_dots: args.combine_dots,
+            flash: 0.,
+            beat: 0.,
+            bar: 0.,
+            cycle: None,
+        }
+    }
+}
+
+impl RenderConfig {
+    // replace a parameter by value, equation indices are rounded
+    pub fn set(&mut self, target: &Target, value: f64) {
+        match target {
+            Target::M => self.m = value,
+            Target::T => self.t = value,
+            Target::Radius => self.radius = value,
+            Target::Size => self.size = value,
+            Target::F1 => self.f1 = value.round() as usize % EQUATIONS,
+            Target::F2 => self.f2 = value.round() as usize % EQUATIONS,
+        }
+    }
+
+    /*
+     * Offset a parameter by value.
+     * Equation indices are replaced, value is the raw index.
+     */
+    pub fn modulate(&mut self, target: &Target, value: f64) {
+        match target {
+            Target::M => self.m += value,
+            Target::T => self.t += value,
+            Target::Radius => self.radius += value,
+            Target::Size => self.size += value,
+            Target::F1 => self.f1 = value.round() as usize % EQUATIONS,
+            Target::F2 => self.f2 = value.round() as usize % EQUATIONS,
         }
     }
 }
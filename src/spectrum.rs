@@ -0,0 +1,100 @@
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::cmp;
+use std::f64::consts::PI;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Blackman,
+}
+
+impl Window {
+    pub fn coefficients(&self, size: usize) -> Vec<f64> {
+        let n = cmp::max(size, 2) as f64 - 1.;
+        (0..size)
+            .map(|i| {
+                let x = 2. * PI * i as f64 / n;
+                match self {
+                    Window::Rectangular => 1.,
+                    Window::Hann => 0.5 - 0.5 * x.cos(),
+                    Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2. * x).cos(),
+                }
+            })
+            .collect()
+    }
+}
+
+/*
+ * Short time fourier transform settings
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Stft {
+    pub window: Window,
+    pub size: usize,
+    pub hop: usize,
+}
+
+impl Stft {
+    pub fn new(window: Window, size: usize, hop: usize) -> Self {
+        let size = cmp::max(size, 2);
+        Self {
+            window,
+            size,
+            // default to 50% overlap
+            hop: if hop > 0 { hop } else { cmp::max(size / 2, 1) },
+        }
+    }
+
+    // number of meaningful bins, DC to nyquist
+    pub fn bins(&self) -> usize {
+        self.size / 2 + 1
+    }
+
+    /*
+     * Windowed, overlapping FFT over samples.
+     * Returns one half spectrum per hop, scaled so a full scale sine peaks near 1.
+     * Blocks shorter than the window are zero padded to a single spectrum.
+     */
+    pub fn process(&self, samples: &[f64]) -> Vec<Vec<Complex<f32>>> {
+        let coefficients = self.window.coefficients(self.size);
+        let gain = 2. / coefficients.iter().sum::<f64>();
+        let mut planner = FftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(self.size);
+
+        let mut spectra = vec![];
+        let mut position = 0;
+        loop {
+            let mut buffer = (0..self.size)
+                .map(|i| Complex {
+                    re: (samples.get(position + i).unwrap_or(&0.) * coefficients[i] * gain) as f32,
+                    im: 0.0f32,
+                })
+                .collect::<Vec<Complex<f32>>>();
+            fft.process(&mut buffer);
+            buffer.truncate(self.bins());
+            spectra.push(buffer);
+
+            position += self.hop;
+            if position + self.size > samples.len() {
+                break;
+            }
+        }
+        spectra
+    }
+
+    /*
+     * Spectrum lookup per sample index: the frame covering the sample,
+     * cycling through the bins of that frame.
+     */
+    pub fn per_sample(&self, samples: &[f64]) -> Vec<Complex<f32>> {
+        let spectra = self.process(samples);
+        let bins = self.bins();
+        (0..samples.len())
+            .map(|index| {
+                let frame = cmp::min(index / self.hop, spectra.len() - 1);
+                spectra[frame][index % bins]
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,182 @@
+use crate::{float_to_i32, frame_window};
+use hound::{SampleFormat, WavSpec};
+use std::cmp;
+use std::io::{ErrorKind, Read};
+
+// sample encoding of headerless PCM
+#[derive(Debug, Clone, Copy)]
+pub enum RawFormat {
+    U8,
+    S16Le,
+    S16Be,
+    S24Le,
+    S32Le,
+    F32Le,
+}
+
+impl RawFormat {
+    pub fn bytes(&self) -> usize {
+        match self {
+            RawFormat::U8 => 1,
+            RawFormat::S16Le | RawFormat::S16Be => 2,
+            RawFormat::S24Le => 3,
+            RawFormat::S32Le | RawFormat::F32Le => 4,
+        }
+    }
+
+    pub fn spec(&self, sample_rate: u32, channels: u16) -> WavSpec {
+        WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: self.bytes() as u16 * 8,
+            sample_format: match self {
+                RawFormat::F32Le => SampleFormat::Float,
+                _ => SampleFormat::Int,
+            },
+        }
+    }
+
+    // same scale as samples read by hound
+    fn decode(&self, b: &[u8]) -> i32 {
+        match self {
+            RawFormat::U8 => b[0] as i32 - 128,
+            RawFormat::S16Le => i16::from_le_bytes([b[0], b[1]]) as i32,
+            RawFormat::S16Be => i16::from_be_bytes([b[0], b[1]]) as i32,
+            RawFormat::S24Le => i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8,
+            RawFormat::S32Le => i32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            RawFormat::F32Le => float_to_i32(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        }
+    }
+}
+
+/*
+ * Raw interleaved PCM read incrementally, only as far as the requested frame reaches.
+ * Frames must be requested in ascending order, samples before a frame are dropped.
+ */
+pub struct PcmStream<R: Read> {
+    reader: R,
+    format: RawFormat,
+    pub spec: WavSpec,
+    fps: usize,
+    overlap: f64,
+    // samples per channel dropped from the front of buffer
+    position: usize,
+    buffer: Vec<i32>,
+    eof: bool,
+}
+
+impl<R: Read> PcmStream<R> {
+    pub fn new(reader: R, format: RawFormat, spec: WavSpec, fps: usize, overlap: f64) -> Self {
+        Self {
+            reader,
+            format,
+            spec,
+            fps,
+            overlap,
+            position: 0,
+            buffer: vec![],
+            eof: false,
+        }
+    }
+
+    fn channels(&self) -> usize {
+        cmp::max(self.spec.channels as usize, 1)
+    }
+
+    fn available(&self) -> usize {
+        self.position + self.buffer.len() / self.channels()
+    }
+
+    // interleaved samples of frame i, None once the stream ends before it
+    pub fn block(&mut self, i: usize) -> Option<Vec<i32>> {
+        let channels = self.channels();
+        let (start, end) = frame_window(i, self.spec.sample_rate, self.fps, self.overlap);
+        let (_, frame_end) = frame_window(i, self.spec.sample_rate, self.fps, 0.);
+        while !self.eof && self.available() < end {
+            self.fill(end - self.available());
+        }
+        if frame_end > self.available() {
+            return None;
+        }
+
+        let skip = cmp::min(
+            start.saturating_sub(self.position),
+            self.buffer.len() / channels,
+        );
+        self.buffer.drain(..skip * channels);
+        self.position += skip;
+        let end = cmp::min(end, self.available()) - self.position;
+        Some(self.buffer[..end * channels].to_vec())
+    }
+
+    fn fill(&mut self, frames: usize) {
+        let frame_bytes = self.channels() * self.format.bytes();
+        let mut bytes = vec![0u8; frames * frame_bytes];
+        let mut read = 0;
+        while read < bytes.len() {
+            match self.reader.read(&mut bytes[read..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => panic!("Could not read sound stream: {}", e),
+            }
+        }
+        // a partial sample frame at the end of the stream is dropped
+        let whole = read / frame_bytes * frame_bytes;
+        let format = self.format;
+        self.buffer.extend(
+            bytes[..whole]
+                .chunks(format.bytes())
+                .map(|b| format.decode(b)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // s16le ramp 0, 1, 2, ... of mono samples
+    fn ramp(samples: i16, fps: usize, overlap: f64) -> PcmStream<Cursor<Vec<u8>>> {
+        let bytes = (0..samples).flat_map(i16::to_le_bytes).collect();
+        let format = RawFormat::S16Le;
+        PcmStream::new(
+            Cursor::new(bytes),
+            format,
+            format.spec(100, 1),
+            fps,
+            overlap,
+        )
+    }
+
+    #[test]
+    fn block_without_overlap() {
+        let mut stream = ramp(40, 10, 0.);
+        assert_eq!(stream.block(0), Some((0..10).collect()));
+        assert_eq!(stream.block(1), Some((10..20).collect()));
+    }
+
+    #[test]
+    fn block_with_overlap() {
+        let mut stream = ramp(40, 10, 0.5);
+        assert_eq!(stream.block(0), Some((0..15).collect()));
+        assert_eq!(stream.block(1), Some((10..25).collect()));
+        // frames may be skipped
+        assert_eq!(stream.block(3), Some((30..40).collect()));
+        assert_eq!(stream.block(4), None);
+    }
+
+    #[test]
+    fn block_stereo() {
+        let bytes: Vec<u8> = (0..8i16).flat_map(i16::to_le_bytes).collect();
+        let format = RawFormat::S16Le;
+        let mut stream = PcmStream::new(Cursor::new(bytes), format, format.spec(4, 2), 2, 0.);
+        assert_eq!(stream.block(0), Some(vec![0, 1, 2, 3]));
+        assert_eq!(stream.block(1), Some(vec![4, 5, 6, 7]));
+        assert_eq!(stream.block(2), None);
+    }
+}
@@ -0,0 +1,191 @@
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(usize),
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Field {
+        name: String,
+        // zero padded
+        width: Option<usize>,
+        // decimals of numbers
+        precision: Option<usize>,
+    },
+}
+
+/*
+ * File names with placeholders, like "{f1}-{f2}/frame_{frame:06}_t{t:.3}.png".
+ * A placeholder is a name with an optional zero padded width and
+ * number of decimals, braces are doubled to write them literally.
+ * The values are given when formatting, by name.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    // names are the placeholders allowed
+    pub fn parse(template: &str, names: &[&str]) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => {
+                                return Err(format!(
+                                    "Could not parse template {}, unmatched {{",
+                                    template
+                                ))
+                            }
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(text.split_off(0)));
+                    }
+                    parts.push(parse_field(&field, names)?);
+                }
+                '}' => {
+                    return Err(format!(
+                        "Could not parse template {}, unmatched }}",
+                        template
+                    ))
+                }
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    // extension of the file names, lowercase, empty without one
+    pub fn extension(&self) -> String {
+        match self.parts.last() {
+            // the text may be only ".png", which is no extension to Path
+            Some(Part::Text(text)) => text
+                .rsplit_once('.')
+                .map(|(_, extension)| extension)
+                .filter(|extension| !extension.contains('/'))
+                .unwrap_or_default()
+                .to_lowercase(),
+            _ => String::new(),
+        }
+    }
+
+    // value gives the value of a placeholder name, checked on parse
+    pub fn format(&self, value: impl Fn(&str) -> Value) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Field {
+                    name,
+                    width,
+                    precision,
+                } => {
+                    let width = width.unwrap_or(0);
+                    match (value(name), precision) {
+                        (Value::Number(number), Some(precision)) => {
+                            format!("{:0width$.precision$}", number)
+                        }
+                        (Value::Number(number), None) => format!("{:0width$}", number),
+                        (Value::Integer(integer), _) => format!("{:0width$}", integer),
+                        (Value::Text(text), _) => format!("{:width$}", text),
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+// name[:[0]width][.precision]
+fn parse_field(field: &str, names: &[&str]) -> Result<Part, String> {
+    let (name, spec) = field.split_once(':').unwrap_or((field, ""));
+    if !names.contains(&name) {
+        return Err(format!(
+            "Could not parse template field {{{}}}, known are {}",
+            field,
+            names.join(", ")
+        ));
+    }
+    let (width, precision) = spec.split_once('.').unwrap_or((spec, ""));
+    let number = |digits: &str| match digits {
+        "" => Ok(None),
+        _ => digits
+            .parse::<usize>()
+            .map(Some)
+            .map_err(|_| format!("Could not parse template field {{{}}}", field)),
+    };
+    Ok(Part::Field {
+        name: name.to_string(),
+        width: number(width)?,
+        precision: number(precision)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: &[&str] = &["frame", "t", "f1", "f2", "method"];
+
+    fn values(name: &str) -> Value {
+        match name {
+            "frame" => Value::Integer(42),
+            "t" => Value::Number(0.5),
+            "f1" => Value::Integer(3),
+            "f2" => Value::Integer(7),
+            _ => Value::Text("arc".to_string()),
+        }
+    }
+
+    #[test]
+    fn format_fields() {
+        let template = Template::parse("{f1}-{f2}/frame_{frame:06}_t{t:.3}.png", NAMES).unwrap();
+        assert_eq!(template.format(values), "3-7/frame_000042_t0.500.png");
+        let template = Template::parse("{method}_{t:08.2}", NAMES).unwrap();
+        assert_eq!(template.format(values), "arc_00000.50");
+    }
+
+    #[test]
+    fn escaped_braces() {
+        let template = Template::parse("{{frame}}_{frame}}}", NAMES).unwrap();
+        assert_eq!(template.format(values), "{frame}_42}");
+    }
+
+    #[test]
+    fn parse_errors() {
+        for template in ["{frame", "frame}", "{radius}", "{frame:x}", "{t:.y}"] {
+            assert!(Template::parse(template, NAMES).is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn extension() {
+        let extension = |template| Template::parse(template, NAMES).unwrap().extension();
+        assert_eq!(extension("frame_{frame:06}.png"), "png");
+        assert_eq!(extension("{frame}.PNG"), "png");
+        assert_eq!(extension("frames.d/{frame}"), "");
+        assert_eq!(extension("{frame}"), "");
+    }
+}
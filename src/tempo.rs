@@ -0,0 +1,239 @@
+use crate::full_scale;
+use hound::WavSpec;
+use std::cmp;
+
+// envelope resolution in samples
+const HOP: usize = 512;
+// tempo search range
+const MIN_BPM: f64 = 60.;
+const MAX_BPM: f64 = 200.;
+// prior, tempos near this are preferred over their multiples
+const PREFERRED_BPM: f64 = 120.;
+// beats spanned to refine the period
+const MULTIPLES: usize = 8;
+
+/*
+ * Musical time grid: tempo and position of the first beat
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Tempo {
+    pub bpm: f64,
+    // seconds
+    pub offset: f64,
+    pub beats_per_bar: usize,
+}
+
+impl Tempo {
+    pub fn new(bpm: f64, offset: f64, beats_per_bar: usize) -> Self {
+        Self {
+            bpm,
+            offset,
+            beats_per_bar: cmp::max(beats_per_bar, 1),
+        }
+    }
+
+    /*
+     * Estimate tempo from interleaved samples.
+     * Autocorrelation of the onset envelope gives the beat period, refined
+     * at its peak a few beats on, a comb over the envelope gives the phase
+     * of the first beat.
+     */
+    pub fn estimate(samples: &[i32], spec: &WavSpec, beats_per_bar: usize) -> Self {
+        let envelope = onset_envelope(samples, spec);
+        let rate = spec.sample_rate as f64 / HOP as f64;
+        let min_lag = cmp::max((rate * 60. / MAX_BPM) as usize, 1);
+        let max_lag = cmp::min((rate * 60. / MIN_BPM) as usize, envelope.len() / 2);
+        if min_lag >= max_lag {
+            return Self::new(PREFERRED_BPM, 0., beats_per_bar);
+        }
+
+        // lags of the refinement too
+        let reach = cmp::min(max_lag * MULTIPLES, envelope.len() / 2);
+        let correlation = (0..=reach + 1)
+            .map(|lag| {
+                envelope
+                    .iter()
+                    .zip(envelope.iter().skip(lag))
+                    .map(|(a, b)| a * b)
+                    .sum::<f64>()
+            })
+            .collect::<Vec<f64>>();
+        let weight = |lag: f64| {
+            let octaves = (60. * rate / lag / PREFERRED_BPM).log2();
+            (-0.5 * octaves * octaves).exp()
+        };
+        let lag = (min_lag..=max_lag)
+            .max_by(|a, b| {
+                (correlation[*a] * weight(*a as f64))
+                    .partial_cmp(&(correlation[*b] * weight(*b as f64)))
+                    .unwrap_or(cmp::Ordering::Equal)
+            })
+            .unwrap_or(min_lag);
+
+        // parabolic interpolation between neighbouring lags
+        let peak = |lag: usize| {
+            let (a, b, c) = (correlation[lag - 1], correlation[lag], correlation[lag + 1]);
+            let denominator = a - 2. * b + c;
+            let shift = if denominator.abs() > f64::EPSILON {
+                (0.5 * (a - c) / denominator).clamp(-0.5, 0.5)
+            } else {
+                0.
+            };
+            lag as f64 + shift
+        };
+        let mut period = peak(lag);
+        // errors shrink by the number of beats at the furthest multiple in reach
+        if let Some(beats) = (2..=MULTIPLES)
+            .rev()
+            .find(|beats| (*beats as f64 * period).round() as usize + beats / 2 < reach)
+        {
+            let center = (beats as f64 * period).round() as usize;
+            let lag = (center - beats / 2..=center + beats / 2)
+                .max_by(|a, b| {
+                    correlation[*a]
+                        .partial_cmp(&correlation[*b])
+                        .unwrap_or(cmp::Ordering::Equal)
+                })
+                .unwrap_or(center);
+            period = peak(lag) / beats as f64;
+        }
+
+        let phase = (0..lag)
+            .max_by(|a, b| {
+                comb(&envelope, *a, period)
+                    .partial_cmp(&comb(&envelope, *b, period))
+                    .unwrap_or(cmp::Ordering::Equal)
+            })
+            .unwrap_or(0);
+
+        // envelope index n is the rise into chunk n + 1
+        Self::new(
+            60. * rate / period,
+            (phase + 1) as f64 / rate,
+            beats_per_bar,
+        )
+    }
+
+    // beats since the first beat
+    pub fn beats(&self, time: f64) -> f64 {
+        (time - self.offset) * self.bpm / 60.
+    }
+
+    // position within the current beat, 0..1
+    pub fn beat_phase(&self, time: f64) -> f64 {
+        self.beats(time).rem_euclid(1.)
+    }
+
+    // position within the current bar, 0..1
+    pub fn bar_phase(&self, time: f64) -> f64 {
+        (self.beats(time) / self.beats_per_bar as f64).rem_euclid(1.)
+    }
+
+    // decaying envelope, 1 on the beat, 0 just before the next
+    pub fn pulse(&self, time: f64) -> f64 {
+        (1. - self.beat_phase(time)).powi(2)
+    }
+
+    /*
+     * Time warped to rush at each beat and settle before the next.
+     * Matches linear time on every beat, so it never drifts.
+     */
+    pub fn warp(&self, time: f64) -> f64 {
+        let beats = self.beats(time);
+        let eased = 1. - (1. - beats.rem_euclid(1.)).powi(3);
+        self.offset + (beats.floor() + eased) * 60. / self.bpm
+    }
+}
+
+/*
+ * Rising log energy of the mono mix per HOP samples, mean removed
+ */
+fn onset_envelope(samples: &[i32], spec: &WavSpec) -> Vec<f64> {
+    let channels = cmp::max(spec.channels as usize, 1);
+    let scale = full_scale(spec);
+    let energy = samples
+        .chunks(HOP * channels)
+        .map(|chunk| {
+            let sum = chunk
+                .iter()
+                .map(|s| (*s as f64 / scale).powi(2))
+                .sum::<f64>();
+            (sum / chunk.len() as f64 + 1e-10).ln()
+        })
+        .collect::<Vec<f64>>();
+    let rising = energy
+        .windows(2)
+        .map(|w| (w[1] - w[0]).max(0.))
+        .collect::<Vec<f64>>();
+    let mean = rising.iter().sum::<f64>() / cmp::max(rising.len(), 1) as f64;
+    rising.iter().map(|e| e - mean).collect()
+}
+
+fn comb(envelope: &[f64], phase: usize, period: f64) -> f64 {
+    let mut position = phase as f64;
+    let mut sum = 0.;
+    while (position.round() as usize) < envelope.len() {
+        sum += envelope[position.round() as usize];
+        position += period;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::SampleFormat;
+
+    const SPEC: WavSpec = WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+
+    // 10 ms clicks on every beat, silence between
+    fn clicks(bpm: f64, offset: f64, seconds: f64) -> Vec<i32> {
+        let rate = SPEC.sample_rate as f64;
+        let mut samples = vec![0; (seconds * rate) as usize];
+        let mut beat = offset;
+        while beat < seconds {
+            let start = (beat * rate) as usize;
+            for (i, sample) in samples.iter_mut().skip(start).take(441).enumerate() {
+                *sample = if i % 20 < 10 { 20000 } else { -20000 };
+            }
+            beat += 60. / bpm;
+        }
+        samples
+    }
+
+    #[test]
+    fn estimate_click_track() {
+        // offsets within two envelope hops
+        let tolerance = 2. * HOP as f64 / SPEC.sample_rate as f64;
+        for (bpm, offset) in [(128., 0.25), (90., 0.1), (174., 0.3)] {
+            let tempo = Tempo::estimate(&clicks(bpm, offset, 60.), &SPEC, 4);
+            assert!((tempo.bpm - bpm).abs() < 0.5, "{:?}", tempo);
+            assert!((tempo.offset - offset).abs() < tolerance, "{:?}", tempo);
+        }
+    }
+
+    #[test]
+    fn phases() {
+        let tempo = Tempo::new(120., 0.5, 4);
+        assert!((tempo.beat_phase(0.75) - 0.5).abs() < 1e-9);
+        assert!((tempo.bar_phase(2.75) - 0.125).abs() < 1e-9);
+        assert!((tempo.bar_phase(0.) - 0.75).abs() < 1e-9);
+        assert!((tempo.pulse(0.5) - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn warp_matches_beats() {
+        let tempo = Tempo::new(128., 0.25, 4);
+        for beat in -2..100 {
+            let time = 0.25 + beat as f64 * 60. / 128.;
+            assert!((tempo.warp(time) - time).abs() < 1e-9, "beat {}", beat);
+        }
+        // rushes ahead between beats
+        assert!(tempo.warp(0.25 + 30. / 128.) > 0.25 + 30. / 128.);
+    }
+}
@@ -0,0 +1,183 @@
+use super::args::parse_method;
+use super::ghostweb::EQUATIONS;
+use super::render::RenderConfig;
+use serde::Deserialize;
+use std::fs;
+
+// how a value moves from the previous keyframe to the one it is set in
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Easing {
+    #[default]
+    Linear,
+    Smoothstep,
+    Cubic,
+    Bounce,
+}
+
+impl Easing {
+    // progress 0..1 to eased progress
+    fn apply(&self, x: f64) -> f64 {
+        let x = x.clamp(0., 1.);
+        match self {
+            Easing::Linear => x,
+            Easing::Smoothstep => x * x * (3. - 2. * x),
+            Easing::Cubic => {
+                if x < 0.5 {
+                    4. * x * x * x
+                } else {
+                    1. - (-2. * x + 2.).powi(3) / 2.
+                }
+            }
+            // ease out, three bounces settling on the target
+            Easing::Bounce => {
+                let (n, d) = (7.5625, 2.75);
+                if x < 1. / d {
+                    n * x * x
+                } else if x < 2. / d {
+                    let x = x - 1.5 / d;
+                    n * x * x + 0.75
+                } else if x < 2.5 / d {
+                    let x = x - 2.25 / d;
+                    n * x * x + 0.9375
+                } else {
+                    let x = x - 2.625 / d;
+                    n * x * x + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/*
+ * Values set at one frame, fields left out keep following
+ * their other keyframes.
+ */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Keyframe {
+    frame: usize,
+    #[serde(default)]
+    easing: Easing,
+    iterations: Option<f64>,
+    radius: Option<f64>,
+    t: Option<f64>,
+    m: Option<f64>,
+    size: Option<f64>,
+    flash: Option<f64>,
+    // switched at the keyframe, not eased
+    f1: Option<usize>,
+    f2: Option<usize>,
+    method: Option<String>,
+    combine_dots: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct File {
+    #[serde(alias = "keyframe")]
+    keyframes: Vec<Keyframe>,
+}
+
+/*
+ * Keyframes for RenderConfig fields from a TOML or JSON file,
+ * a list of keyframe tables:
+ *
+ * [[keyframe]]
+ * frame = 120
+ * radius = 400
+ * easing = "smoothstep"
+ * method = "arc"
+ *
+ * Numbers ease between the keyframes that set them, with the easing of
+ * the later one, and hold before the first and after the last.
+ */
+#[derive(Debug)]
+pub struct Timeline {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Timeline {
+    pub fn load(filename: &str) -> Self {
+        let text = fs::read_to_string(filename).expect("Could not open timeline file");
+        let file: File = if filename.ends_with(".json") {
+            serde_json::from_str(&text)
+                .unwrap_or_else(|e| panic!("Could not parse timeline {}: {}", filename, e))
+        } else {
+            toml::from_str(&text)
+                .unwrap_or_else(|e| panic!("Could not parse timeline {}: {}", filename, e))
+        };
+        let mut keyframes = file.keyframes;
+        for method in keyframes.iter().filter_map(|k| k.method.as_ref()) {
+            parse_method(method).unwrap_or_else(|e| panic!("{}", e));
+        }
+        keyframes.sort_by_key(|keyframe| keyframe.frame);
+        Self { keyframes }
+    }
+
+    // eased value of a numeric field at frame
+    fn value(&self, frame: usize, field: fn(&Keyframe) -> Option<f64>) -> Option<f64> {
+        let mut previous: Option<(&Keyframe, f64)> = None;
+        for keyframe in self.keyframes.iter() {
+            let value = match field(keyframe) {
+                Some(value) => value,
+                None => continue,
+            };
+            if keyframe.frame > frame {
+                return Some(match previous {
+                    None => value,
+                    Some((from, from_value)) => {
+                        let progress =
+                            (frame - from.frame) as f64 / (keyframe.frame - from.frame) as f64;
+                        from_value + (value - from_value) * keyframe.easing.apply(progress)
+                    }
+                });
+            }
+            previous = Some((keyframe, value));
+        }
+        previous.map(|(_, value)| value)
+    }
+
+    // last switch at or before frame
+    fn switch<T>(&self, frame: usize, field: fn(&Keyframe) -> Option<T>) -> Option<T> {
+        self.keyframes
+            .iter()
+            .take_while(|keyframe| keyframe.frame <= frame)
+            .filter_map(field)
+            .last()
+    }
+
+    // replace the fields the timeline sets
+    pub fn apply(&self, frame: usize, config: &mut RenderConfig) {
+        if let Some(iterations) = self.value(frame, |k| k.iterations) {
+            config.iterations = iterations.round().max(0.) as u32;
+        }
+        if let Some(radius) = self.value(frame, |k| k.radius) {
+            config.radius = radius;
+        }
+        if let Some(t) = self.value(frame, |k| k.t) {
+            config.t = t;
+        }
+        if let Some(m) = self.value(frame, |k| k.m) {
+            config.m = m;
+        }
+        if let Some(size) = self.value(frame, |k| k.size) {
+            config.size = size;
+        }
+        if let Some(flash) = self.value(frame, |k| k.flash) {
+            config.flash = flash;
+        }
+        if let Some(f1) = self.switch(frame, |k| k.f1) {
+            config.f1 = f1 % EQUATIONS;
+        }
+        if let Some(f2) = self.switch(frame, |k| k.f2) {
+            config.f2 = f2 % EQUATIONS;
+        }
+        if let Some(method) = self.switch(frame, |k| k.method.clone()) {
+            // checked on load
+            config.method = parse_method(&method).unwrap();
+        }
+        if let Some(combine_dots) = self.switch(frame, |k| k.combine_dots) {
+            config.combine_dots = combine_dots;
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use cairo::ImageSurface;
+use color_quant::NeuQuant;
+use image::imageops::{self, ColorMap};
+use image::RgbaImage;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+// 1 is best and slowest, 30 fastest
+const QUANTIZE_SPEED: i32 = 10;
+
+/*
+ * Straight RGBA bytes of a cairo surface.
+ * ARGB32 pixels are premultiplied native endian u32.
+ */
+pub fn rgba(surface: &mut ImageSurface) -> RgbaImage {
+    let (width, height) = (surface.width() as u32, surface.height() as u32);
+    let stride = surface.stride() as usize;
+    surface.flush();
+    let data = surface.data().expect("Could not read frame");
+    RgbaImage::from_fn(width, height, |x, y| {
+        let offset = y as usize * stride + x as usize * 4;
+        let pixel = u32::from_ne_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]);
+        let alpha = (pixel >> 24) & 0xff;
+        let channel = |shift: u32| match alpha {
+            0 => 0,
+            _ => (((pixel >> shift) & 0xff) * 255 / alpha) as u8,
+        };
+        image::Rgba([channel(16), channel(8), channel(0), alpha as u8])
+    })
+}
+
+/*
+ * Frames as an uncompressed YUV4MPEG2 stream, 4:2:0 with BT.601 studio range,
+ * for piping into an encoder.
+ */
+pub struct Y4m<W: Write> {
+    writer: W,
+    fps: usize,
+    header: bool,
+}
+
+impl<W: Write> Y4m<W> {
+    pub fn new(writer: W, fps: usize) -> Self {
+        Self {
+            writer,
+            fps,
+            header: false,
+        }
+    }
+
+    pub fn write_frame(&mut self, image: &RgbaImage) {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if !self.header {
+            writeln!(
+                self.writer,
+                "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg",
+                width, height, self.fps
+            )
+            .expect("Could not write video header");
+            self.header = true;
+        }
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        let mut y = vec![0u8; width * height];
+        let mut u = vec![0f64; chroma_width * chroma_height];
+        let mut v = vec![0f64; chroma_width * chroma_height];
+        let mut count = vec![0f64; chroma_width * chroma_height];
+
+        for (column, row, pixel) in image.enumerate_pixels() {
+            let (column, row) = (column as usize, row as usize);
+            let [r, g, b, _] = pixel.0.map(|c| c as f64);
+            y[row * width + column] = (16. + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8;
+            let chroma = row / 2 * chroma_width + column / 2;
+            u[chroma] += 128. - 0.148 * r - 0.291 * g + 0.439 * b;
+            v[chroma] += 128. + 0.439 * r - 0.368 * g - 0.071 * b;
+            count[chroma] += 1.;
+        }
+        let average = |plane: Vec<f64>| {
+            plane
+                .iter()
+                .zip(count.iter())
+                .map(|(sum, n)| (sum / n).round().clamp(0., 255.) as u8)
+                .collect::<Vec<u8>>()
+        };
+
+        self.writer
+            .write_all(b"FRAME\n")
+            .and_then(|_| self.writer.write_all(&y))
+            .and_then(|_| self.writer.write_all(&average(u)))
+            .and_then(|_| self.writer.write_all(&average(v)))
+            .and_then(|_| self.writer.flush())
+            .expect("Could not write video frame");
+    }
+}
+
+/*
+ * Animated GIF, looping forever.
+ * Every frame gets its own 256 color palette, optionally Floyd-Steinberg dithered.
+ */
+pub struct Gif<W: Write> {
+    encoder: gif::Encoder<W>,
+    fps: usize,
+    dither: bool,
+    frame: usize,
+}
+
+impl<W: Write> Gif<W> {
+    pub fn new(writer: W, width: u32, height: u32, fps: usize, dither: bool) -> Self {
+        let mut encoder = gif::Encoder::new(writer, width as u16, height as u16, &[])
+            .expect("Could not write GIF");
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .expect("Could not write GIF");
+        Self {
+            encoder,
+            fps,
+            dither,
+            frame: 0,
+        }
+    }
+
+    pub fn write_frame(&mut self, image: &RgbaImage) {
+        let (width, height) = (image.width() as u16, image.height() as u16);
+
+        let quantizer = NeuQuant::new(QUANTIZE_SPEED, 256, image.as_raw());
+        let mut image = image.clone();
+        if self.dither {
+            imageops::dither(&mut image, &quantizer);
+        }
+        let indices = image
+            .pixels()
+            .map(|pixel| ColorMap::index_of(&quantizer, pixel) as u8)
+            .collect::<Vec<u8>>();
+        let palette = quantizer.color_map_rgb();
+
+        let mut frame = gif::Frame::from_indexed_pixels(width, height, indices, None);
+        frame.palette = Some(palette);
+        // delays are in hundredths of a second, rounded without drifting
+        let at = |frame: usize| (frame as f64 * 100. / self.fps as f64).round() as u16;
+        frame.delay = at(self.frame + 1) - at(self.frame);
+        self.frame += 1;
+        self.encoder
+            .write_frame(&frame)
+            .expect("Could not write GIF frame");
+    }
+
+    pub fn finish(self) {
+        self.encoder
+            .into_inner()
+            .and_then(|mut writer| writer.flush())
+            .expect("Could not write GIF");
+    }
+}
+
+/*
+ * Animated PNG, looping forever.
+ * The frame count goes into the header, frames are written as they come
+ * when it is known and kept until finish otherwise.
+ */
+pub enum Apng<W: Write> {
+    Counted {
+        writer: png::Writer<W>,
+        frames: usize,
+        written: usize,
+    },
+    Buffered {
+        writer: W,
+        fps: usize,
+        frames: Vec<RgbaImage>,
+    },
+}
+
+impl<W: Write> Apng<W> {
+    pub fn new(writer: W, width: u32, height: u32, fps: usize, frames: Option<usize>) -> Self {
+        match frames {
+            Some(frames) if frames > 0 => Apng::Counted {
+                writer: apng_header(writer, width, height, fps, frames),
+                frames,
+                written: 0,
+            },
+            _ => Apng::Buffered {
+                writer,
+                fps,
+                frames: vec![],
+            },
+        }
+    }
+
+    pub fn write_frame(&mut self, image: &RgbaImage) {
+        match self {
+            Apng::Counted {
+                writer, written, ..
+            } => {
+                writer
+                    .write_image_data(image.as_raw())
+                    .expect("Could not write APNG frame");
+                *written += 1;
+            }
+            Apng::Buffered { frames, .. } => frames.push(image.clone()),
+        }
+    }
+
+    pub fn finish(self) {
+        match self {
+            Apng::Counted {
+                writer,
+                frames,
+                written,
+            } => {
+                if written < frames {
+                    panic!(
+                        "Could not write APNG, the sound ended after {} of {} frames",
+                        written, frames
+                    );
+                }
+                writer.finish().expect("Could not write APNG");
+            }
+            Apng::Buffered {
+                writer,
+                fps,
+                frames,
+            } => {
+                let (width, height) = match frames.first() {
+                    Some(first) => first.dimensions(),
+                    None => return,
+                };
+                let mut writer = apng_header(writer, width, height, fps, frames.len());
+                for frame in frames.iter() {
+                    writer
+                        .write_image_data(frame.as_raw())
+                        .expect("Could not write APNG frame");
+                }
+                writer.finish().expect("Could not write APNG");
+            }
+        }
+    }
+}
+
+fn apng_header<W: Write>(
+    writer: W,
+    width: u32,
+    height: u32,
+    fps: usize,
+    frames: usize,
+) -> png::Writer<W> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames as u32, 0)
+        .and_then(|_| encoder.set_frame_delay(1, fps as u16))
+        .expect("Could not write APNG");
+    encoder.write_header().expect("Could not write APNG")
+}
+
+/*
+ * All frames of a render in one file, the format picked by extension:
+ * .gif, .png or .apng, Y4M otherwise and for "-", which is stdout.
+ */
+pub enum Video {
+    Y4m(Y4m<Box<dyn Write>>),
+    Gif(Gif<BufWriter<File>>),
+    Apng(Apng<BufWriter<File>>),
+}
+
+impl Video {
+    // frames is the frame count, None when it is unknown
+    pub fn create(
+        path: &str,
+        width: u32,
+        height: u32,
+        fps: usize,
+        frames: Option<usize>,
+        dither: bool,
+    ) -> Self {
+        if path == "-" {
+            return Video::Y4m(Y4m::new(Box::new(io::stdout()), fps));
+        }
+        let file = BufWriter::new(File::create(path).expect("Could not open video file"));
+        let extension = path.rsplit('.').next().unwrap_or_default();
+        match extension.to_lowercase().as_str() {
+            "gif" => Video::Gif(Gif::new(file, width, height, fps, dither)),
+            "png" | "apng" => Video::Apng(Apng::new(file, width, height, fps, frames)),
+            _ => Video::Y4m(Y4m::new(Box::new(file), fps)),
+        }
+    }
+
+    pub fn write_frame(&mut self, mut surface: ImageSurface) {
+        let image = rgba(&mut surface);
+        match self {
+            Video::Y4m(video) => video.write_frame(&image),
+            Video::Gif(video) => video.write_frame(&image),
+            Video::Apng(video) => video.write_frame(&image),
+        }
+    }
+
+    pub fn finish(self) {
+        match self {
+            // flushed every frame
+            Video::Y4m(_) => {}
+            Video::Gif(video) => video.finish(),
+            Video::Apng(video) => video.finish(),
+        }
+    }
+}